@@ -0,0 +1,461 @@
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Parser;
+use clap::ValueEnum;
+use url::Url;
+
+use ping_pong_client::reconnect::Backoff;
+use ping_pong_client::target::parse_server_name;
+use ping_pong_client::target::IpVersion;
+use ping_pong_client::throughput::BulkOptions;
+use ping_pong_client::throughput::BulkTransport;
+use ping_pong_client::throughput::Limit;
+use ping_pong_client::timeouts::Timeouts;
+use ping_pong_client::tls;
+use ping_pong_client::tls::Trust;
+use wtransport::tls::Sha256Digest;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0   at least one ping was answered; with --config, every target was healthy
+  1   with --config, some targets were degraded or down
+  2   invalid command line
+  3   local setup failed: bind or metrics address, CA, client certificate, key log or qlog files
+  4   server host could not be resolved
+  5   server unreachable or connection lost
+  6   TLS handshake failed or client certificate rejected
+  7   WebTransport session rejected by the server
+  8   stream or datagram failure on the session
+  9   unexpected reply from the server
+  10  timed out waiting for the server
+  130 interrupted again while the pings in flight were finishing";
+
+/// Smallest write of a stream throughput test; smaller ones time the per-write
+/// overhead of the client rather than the path.
+const MIN_CHUNK_SIZE: u64 = 64;
+
+/// WebTransport ping-pong client.
+#[derive(Parser, Debug)]
+#[command(version, about, after_help = EXIT_CODES)]
+pub struct Cli {
+    /// WebTransport URL of the server, e.g. `https://localhost:4433/`; several with `--metrics`.
+    #[arg(value_parser = parse_url, default_value = "https://localhost:4433/", num_args = 1.., value_name = "URL")]
+    pub urls: Vec<Url>,
+
+    /// Local socket address to bind the client endpoint to.
+    ///
+    /// Defaults to the unspecified address of the target's family with an ephemeral port.
+    #[arg(short, long)]
+    pub bind: Option<SocketAddr>,
+
+    /// Server name used for TLS (SNI) and as the authority of the session request.
+    ///
+    /// Defaults to the host of the URL.
+    #[arg(long, value_parser = parse_server_name)]
+    pub server_name: Option<String>,
+
+    /// PEM bundle of the CAs to verify the server certificate against, instead of the
+    /// system's root CAs.
+    #[arg(long, value_name = "PEM")]
+    pub ca_file: Option<PathBuf>,
+
+    /// Accept the server certificate with this SHA-256 hash, in hex with or without colons.
+    ///
+    /// Follows the WebTransport `serverCertificateHashes` rules: the certificate must be
+    /// valid for at most two weeks and use an ECDSA P-256 key. May be repeated.
+    #[arg(long, value_name = "SHA256", value_parser = tls::parse_digest, conflicts_with = "ca_file")]
+    pub pin: Vec<Sha256Digest>,
+
+    /// Accept any server certificate. Only for local development.
+    #[arg(short = 'k', long, conflicts_with_all = ["ca_file", "pin"])]
+    pub insecure: bool,
+
+    /// Client certificate chain, PEM or DER, presented to servers that authenticate clients.
+    #[arg(long, value_name = "FILE", requires = "key")]
+    pub cert: Option<PathBuf>,
+
+    /// Private key of the client certificate: PEM or DER, PKCS#8, PKCS#1 or SEC1.
+    #[arg(long, value_name = "FILE", requires = "cert")]
+    pub key: Option<PathBuf>,
+
+    /// File holding the password of an encrypted PKCS#8 `--key`.
+    #[arg(long, value_name = "FILE", requires = "key")]
+    pub key_password_file: Option<PathBuf>,
+
+    /// Append the TLS secrets of every connection to this file in the NSS key log
+    /// format, so that Wireshark can decrypt captures of them.
+    ///
+    /// Defaults to the `SSLKEYLOGFILE` environment variable. Anyone who can read the
+    /// file can decrypt the captured traffic.
+    #[arg(long, value_name = "FILE")]
+    pub keylog: Option<PathBuf>,
+
+    /// Write a qlog trace of every connection to this directory, as JSON-SEQ files
+    /// named `<connection ID>-client.sqlog`, for viewing in qvis.
+    ///
+    /// The connection ID is the destination ID of the client's first packets, which
+    /// the server's `--quic-log` names its trace after. The trace holds the connection,
+    /// stream and datagram events the client sees, the packets sent, received and
+    /// lost, and RTT and congestion window updates.
+    #[arg(short = 'q', long, value_name = "DIR")]
+    pub qlog_dir: Option<PathBuf>,
+
+    /// Only use IPv4 addresses of the target.
+    #[arg(short = '4', long, conflicts_with = "ipv6")]
+    pub ipv4: bool,
+
+    /// Only use IPv6 addresses of the target.
+    #[arg(short = '6', long)]
+    pub ipv6: bool,
+
+    /// Number of pings to send; 0 keeps pinging until Ctrl-C or SIGTERM.
+    #[arg(short, long, default_value_t = 1)]
+    pub count: u64,
+
+    /// Transport used to carry the pings.
+    #[arg(short, long, value_enum, default_value_t = Transport::Bi)]
+    pub transport: Transport,
+
+    /// Seconds to wait between pings, or between throughput reports; more than 0.
+    #[arg(short, long, value_parser = parse_interval, default_value = "1")]
+    pub interval: Duration,
+
+    /// Ping over this many bidirectional streams at once in each round, to observe how
+    /// the streams are multiplexed; `--count` and `--interval` then apply to rounds.
+    #[arg(long, value_name = "STREAMS", value_parser = parse_positive, conflicts_with_all = ["transport", "throughput"])]
+    pub fanout: Option<usize>,
+
+    /// Tell the cost of setting up a connection from the steady-state round trip: each
+    /// round pings over a new connection, then over one connection kept for the run.
+    ///
+    /// Name resolution, the handshake up to the server certificate and the rest of the
+    /// handshake with the session request are timed apart. `--count` and `--interval`
+    /// then apply to rounds.
+    #[arg(long, conflicts_with_all = ["transport", "throughput", "fanout", "config", "metrics", "reconnect"])]
+    pub compare_reuse: bool,
+
+    /// Resume the TLS session of an earlier connection of the run instead of running a
+    /// full handshake: in the connections of `--compare-reuse`, which then also times
+    /// resumed connections apart, and in the reconnections of `--reconnect`
+    /// and `--metrics`.
+    ///
+    /// Connections report whether they had a session to offer, whether the server
+    /// accepted it and whether it allowed 0-RTT data in it.
+    ///
+    /// Not supported: keeping sessions on disk across runs, as rustls can neither
+    /// export a session nor restore one, and sending 0-RTT data, as the wtransport
+    /// client sends the session request only once the handshake is done.
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Connections a fan-out round pings over, each with `--fanout` streams.
+    #[arg(long, value_parser = parse_positive, default_value = "1", requires = "fanout")]
+    pub connections: usize,
+
+    /// Probe the fleet of servers listed in this TOML file, or YAML file when it ends
+    /// in `.yaml` or `.yml`, all at once, and report each one's health and the fleet's.
+    ///
+    /// Each `[[target]]` has a `url` and optionally a `name`, `server_name`, `expect`,
+    /// `transport`, `count`, `interval`, `timeout` (of the whole probe), the
+    /// `connect_timeout` and other phase timeouts, `ca_file`, `pin`, `insecure`, `cert`,
+    /// `key` and `key_password_file`; `expect` does not apply to datagram pings. A
+    /// `[defaults]` table sets them for every target. In YAML, `target` is a list and
+    /// `defaults` a mapping. The probe options of the command line do not apply.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["throughput", "fanout", "metrics"])]
+    pub config: Option<PathBuf>,
+
+    /// Probe the servers every `--interval` until interrupted, serving the results as
+    /// Prometheus metrics on `/metrics` of this address, e.g. `127.0.0.1:9464`.
+    ///
+    /// Each probe is a stream ping and a datagram ping over a connection kept between
+    /// probes and re-established once lost, retrying as set by `--reconnect-delay`.
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["throughput", "fanout", "overall_timeout"])]
+    pub metrics: Option<SocketAddr>,
+
+    /// Re-establish a lost connection and keep pinging, reporting the cause and the
+    /// duration of each outage.
+    ///
+    /// A connection is lost once the server closes it, or after the QUIC idle timeout
+    /// of 30 seconds without any packet from the server.
+    #[arg(long, conflicts_with_all = ["throughput", "fanout", "config", "metrics"])]
+    pub reconnect: bool,
+
+    /// Seconds before the first attempt to re-establish a lost connection, with
+    /// `--reconnect` or `--metrics`; the delay doubles after each failed attempt and
+    /// is jittered by up to a half.
+    #[arg(long, value_parser = parse_seconds, default_value = "0.5", value_name = "SECS")]
+    pub reconnect_delay: Duration,
+
+    /// Cap in seconds on the delay between attempts to re-establish a lost connection.
+    #[arg(long, value_parser = parse_seconds, default_value = "30", value_name = "SECS")]
+    pub reconnect_max_delay: Duration,
+
+    /// Give up re-establishing a lost connection after this many failed attempts;
+    /// unbounded by default.
+    #[arg(long, value_name = "ATTEMPTS", requires = "reconnect")]
+    pub reconnect_attempts: Option<u32>,
+
+    /// Measure throughput over this transport instead of pinging.
+    ///
+    /// Streams need the native server, which sinks or echoes them; datagrams are echoed
+    /// by both servers. The byte count or the rest of the echo of a stream must arrive
+    /// within `--reply-timeout` once sending ended.
+    #[arg(long, value_enum, value_name = "TRANSPORT")]
+    pub throughput: Option<Throughput>,
+
+    /// Streams sending in parallel in a throughput test.
+    #[arg(long, value_parser = parse_positive, default_value = "1", requires = "throughput")]
+    pub streams: usize,
+
+    /// Bytes a throughput test sends, e.g. `100M`; K, M and G are powers of 1024.
+    #[arg(long, value_parser = parse_bytes, conflicts_with = "time", requires = "throughput")]
+    pub bytes: Option<u64>,
+
+    /// Seconds a throughput test sends for.
+    #[arg(long, value_parser = parse_seconds, default_value = "10", value_name = "SECS")]
+    pub time: Duration,
+
+    /// Bytes per stream write, and the datagram size as far as the path allows; at
+    /// least 64.
+    #[arg(long, value_parser = parse_chunk_size, default_value = "16K", value_name = "BYTES")]
+    pub chunk_size: u64,
+
+    /// Have the server echo bidirectional streams back instead of sinking them.
+    #[arg(long, requires = "throughput")]
+    pub echo: bool,
+
+    /// Datagram throughput rate in bits per second, e.g. `10M`; K, M and G are powers of 1000.
+    #[arg(long, value_parser = parse_bitrate, default_value = "1M", value_name = "RATE")]
+    pub bitrate: u64,
+
+    /// Format of the results on standard output.
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+
+    /// Log the phases of connections and pings to standard error: once for each one
+    /// with its duration and any failure, twice for each step within them, three
+    /// times for everything including QUIC and TLS internals.
+    ///
+    /// `RUST_LOG` takes precedence, e.g. `RUST_LOG=ping_pong_client=debug,quinn=trace`.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Format of the log on standard error.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Seconds allowed to resolve the server and get its certificate; 0 disables [default: 5].
+    #[arg(long, value_parser = parse_seconds, value_name = "SECS")]
+    pub connect_timeout: Option<Duration>,
+
+    /// Seconds allowed to get the WebTransport session accepted once the server
+    /// certificate arrived; 0 disables [default: 5].
+    #[arg(long, value_parser = parse_seconds, value_name = "SECS")]
+    pub session_timeout: Option<Duration>,
+
+    /// Seconds allowed to open the stream of a ping; 0 disables [default: 5].
+    #[arg(long, value_parser = parse_seconds, value_name = "SECS")]
+    pub stream_open_timeout: Option<Duration>,
+
+    /// Seconds to wait for the reply to a ping; 0 disables [default: 5]. Datagram pings
+    /// still wait at most 5 seconds for the echoes of the last ones.
+    #[arg(short = 'W', long, value_parser = parse_seconds, value_name = "SECS")]
+    pub reply_timeout: Option<Duration>,
+
+    /// Seconds to wait for the connection to finish closing once the session is
+    /// closed; 0 waits as long as QUIC takes [default: 1].
+    #[arg(long, value_parser = parse_seconds, value_name = "SECS")]
+    pub close_timeout: Option<Duration>,
+
+    /// Seconds after which the whole run stops, connection included; unbounded by default.
+    #[arg(short = 'w', long, value_parser = parse_seconds, value_name = "SECS")]
+    pub overall_timeout: Option<Duration>,
+}
+
+/// Transport carrying the pings.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// A new bidirectional stream per ping.
+    Bi,
+    /// A new unidirectional stream per ping, answered on one the server opens; needs
+    /// the native server.
+    Uni,
+    /// Sequence-numbered WebTransport datagrams echoed by the server.
+    Datagram,
+}
+
+/// Format of the results.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Human-readable lines, like ping(8).
+    Text,
+    /// One JSON object per line: per ping or throughput interval, then a summary.
+    Json,
+    /// One CSV row per ping or throughput interval; the summary goes to standard error.
+    Csv,
+}
+
+/// Format of the log.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines with the enclosing spans.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Transport of a throughput test.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throughput {
+    /// Bidirectional streams, sunk or echoed by the server.
+    Bi,
+    /// Unidirectional streams sunk by the server.
+    Uni,
+    /// Datagrams echoed by the server.
+    Datagram,
+}
+
+impl Cli {
+    /// The server URL, the first one when several were given.
+    pub fn url(&self) -> &Url {
+        &self.urls[0]
+    }
+
+    /// Throughput test asked for on the command line, if any.
+    pub fn bulk_options(&self) -> Option<BulkOptions> {
+        let transport = match self.throughput? {
+            Throughput::Bi => BulkTransport::Bi,
+            Throughput::Uni => BulkTransport::Uni,
+            Throughput::Datagram => BulkTransport::Datagram,
+        };
+        let limit = match self.bytes {
+            Some(bytes) => Limit::Bytes(bytes),
+            None => Limit::Duration(self.time),
+        };
+        Some(BulkOptions {
+            transport,
+            streams: self.streams,
+            limit,
+            chunk_size: usize::try_from(self.chunk_size).unwrap_or(usize::MAX),
+            echo: self.echo,
+            bitrate: self.bitrate,
+            interval: self.interval,
+        })
+    }
+
+    /// Deadlines from the command line, falling back to [`Timeouts::default`].
+    pub fn timeouts(&self) -> Timeouts {
+        let defaults = Timeouts::default();
+        let pick = |arg: Option<Duration>, default: Option<Duration>| match arg {
+            Some(Duration::ZERO) => None,
+            Some(limit) => Some(limit),
+            None => default,
+        };
+        Timeouts {
+            connect: pick(self.connect_timeout, defaults.connect),
+            session: pick(self.session_timeout, defaults.session),
+            stream_open: pick(self.stream_open_timeout, defaults.stream_open),
+            reply: pick(self.reply_timeout, defaults.reply),
+            close: pick(self.close_timeout, defaults.close),
+            overall: pick(self.overall_timeout, defaults.overall),
+        }
+    }
+
+    /// Delays between attempts to re-establish a lost connection.
+    pub fn backoff(&self) -> Backoff {
+        Backoff {
+            initial: self.reconnect_delay,
+            max: self.reconnect_max_delay.max(self.reconnect_delay),
+            attempts: self.reconnect_attempts,
+        }
+    }
+
+    /// File the TLS secrets are logged to: `--keylog`, else `SSLKEYLOGFILE` if set.
+    pub fn keylog_path(&self) -> Option<PathBuf> {
+        match &self.keylog {
+            Some(path) => Some(path.clone()),
+            None => env::var_os("SSLKEYLOGFILE").filter(|path| !path.is_empty()).map(PathBuf::from),
+        }
+    }
+
+    pub fn trust(&self) -> Trust {
+        match (&self.ca_file, self.pin.is_empty(), self.insecure) {
+            (_, _, true) => Trust::Insecure,
+            (Some(path), _, _) => Trust::CaFile(path.clone()),
+            (None, false, _) => Trust::Pinned(self.pin.clone()),
+            (None, true, false) => Trust::System,
+        }
+    }
+
+    pub fn ip_version(&self) -> IpVersion {
+        match (self.ipv4, self.ipv6) {
+            (true, _) => IpVersion::V4,
+            (_, true) => IpVersion::V6,
+            _ => IpVersion::Any,
+        }
+    }
+}
+
+fn parse_url(s: &str) -> Result<Url, String> {
+    let url = Url::parse(s).map_err(|e| e.to_string())?;
+    if url.scheme() != "https" {
+        return Err("URL scheme must be 'https'".to_string());
+    }
+    if url.host().is_none() {
+        return Err("URL must have a host".to_string());
+    }
+    Ok(url)
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s.parse().map_err(|_| format!("'{}' is not a number of seconds", s))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| e.to_string())
+}
+
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match parse_seconds(s)? {
+        interval if interval.is_zero() => Err("the interval must be greater than 0".to_string()),
+        interval => Ok(interval),
+    }
+}
+
+fn parse_positive(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("'{}' is not a count", s)),
+    }
+}
+
+fn parse_bytes(s: &str) -> Result<u64, String> {
+    parse_quantity(s, 1024)
+}
+
+fn parse_chunk_size(s: &str) -> Result<u64, String> {
+    match parse_bytes(s)? {
+        size if size < MIN_CHUNK_SIZE => Err(format!("must be at least {} bytes", MIN_CHUNK_SIZE)),
+        size => Ok(size),
+    }
+}
+
+fn parse_bitrate(s: &str) -> Result<u64, String> {
+    match parse_quantity(s, 1000)? {
+        0 => Err("the bitrate must be greater than 0".to_string()),
+        bitrate => Ok(bitrate),
+    }
+}
+
+/// Parses a count with an optional K, M or G suffix, each a power of `base`.
+fn parse_quantity(s: &str, base: u64) -> Result<u64, String> {
+    let (digits, exponent) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1),
+        Some((i, 'm' | 'M')) => (&s[..i], 2),
+        Some((i, 'g' | 'G')) => (&s[..i], 3),
+        _ => (s, 0),
+    };
+    let count: u64 = digits.parse().map_err(|_| format!("'{}' is not a count like 64K or 10M", s))?;
+    count.checked_mul(base.pow(exponent)).ok_or_else(|| format!("'{}' is too large", s))
+}
@@ -0,0 +1,700 @@
+use std::fmt;
+use std::future;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use quinn::TransportConfig;
+use tokio::sync::Mutex;
+use tokio::time;
+use tracing::debug;
+use tracing::field;
+use tracing::info;
+use tracing::info_span;
+use tracing::Instrument;
+use tracing::Span;
+use url::Url;
+use wtransport::endpoint::endpoint_side;
+use wtransport::error::ConnectionError;
+use wtransport::ClientConfig;
+use wtransport::Connection;
+use wtransport::Endpoint;
+use wtransport::RecvStream;
+use wtransport::SendStream;
+use wtransport::VarInt;
+
+use crate::datagram;
+use crate::errors::ClientError;
+use crate::qlog::Owner;
+use crate::qlog::Trace;
+use crate::target::IpVersion;
+use crate::target::Target;
+use crate::throughput;
+use crate::throughput::BulkOptions;
+use crate::throughput::BulkReport;
+use crate::throughput::Interval;
+use crate::timeouts;
+use crate::timeouts::Phase;
+use crate::timeouts::Timeouts;
+use crate::tls;
+use crate::tls::ClientIdentity;
+use crate::tls::HandshakeProgress;
+use crate::tls::KeyLog;
+use crate::tls::SessionCache;
+use crate::tls::Trust;
+
+pub const PING: &[u8] = b"ping";
+pub const PONG: &[u8] = b"pong";
+
+/// Answer to a stream ping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pong {
+    /// QUIC stream ID of the stream the ping was sent on.
+    pub stream_id: u64,
+    pub rtt: Duration,
+}
+
+/// Application error code of a session closed normally.
+pub const CLOSE_NORMAL: u32 = 0;
+
+/// How long a complete reply waits for the server to end its stream, which tells a
+/// reply ending there from one followed by more data.
+const REPLY_END_WAIT: Duration = Duration::from_millis(100);
+
+/// How a session ended, as found by [`PingClient::close`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Closed {
+    /// The client closed the session with `code` and `reason`; `drained` tells
+    /// whether the connection finished closing within the close deadline.
+    ByClient { code: u32, reason: String, drained: bool },
+    /// The server had closed the session first, with `code` and `reason`.
+    ByServer { code: u64, reason: String },
+    /// The connection had been lost without the server closing it.
+    Lost(ConnectionError),
+}
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Closed::ByClient { code, reason, drained } => {
+                write!(f, "closed by the client with code {}", code)?;
+                if !reason.is_empty() {
+                    write!(f, " ({})", reason)?;
+                }
+                match drained {
+                    true => Ok(()),
+                    false => write!(f, "; the connection had not finished closing in time"),
+                }
+            }
+            Closed::ByServer { code, reason } if reason.is_empty() => write!(f, "closed by the server with code {}", code),
+            Closed::ByServer { code, reason } => write!(f, "closed by the server with code {} ({})", code, reason),
+            Closed::Lost(e) => write!(f, "lost before closing: {}", e),
+        }
+    }
+}
+
+/// Time spent in each step of [`PingClient::connect`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectTimings {
+    /// Resolving the host and binding the endpoint.
+    pub resolve: Duration,
+    /// QUIC and TLS handshake until the server certificate was verified.
+    pub handshake: Duration,
+    /// End of the handshake and the WebTransport session request (extended CONNECT).
+    ///
+    /// A resumed session skips the server certificate, so its handshake cannot be
+    /// told apart from the session request: `handshake` covers both and this is zero.
+    pub session: Duration,
+    /// Whether the TLS session of an earlier connection was resumed.
+    pub resumed: bool,
+    /// Whether a TLS session of an earlier connection was offered to the server, which
+    /// refused it if the connection is not `resumed`.
+    pub resumption_offered: bool,
+    /// Whether the server allowed 0-RTT data in the offered session. The client sends
+    /// none, as wtransport sends the session request only once the handshake is done.
+    pub zero_rtt_allowed: bool,
+}
+
+impl ConnectTimings {
+    pub fn total(&self) -> Duration {
+        self.resolve + self.handshake + self.session
+    }
+}
+
+/// Options applied when connecting a [`PingClient`].
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// Local socket address to bind to; defaults to the unspecified address of the
+    /// target's family with an ephemeral port.
+    pub bind: Option<SocketAddr>,
+    /// TLS server name; defaults to the host of the URL.
+    pub server_name: Option<String>,
+    /// Address family the URL host may resolve to.
+    pub ip_version: IpVersion,
+    /// Deadlines of the connection and of each ping.
+    pub timeouts: Timeouts,
+    /// Server certificates to accept.
+    pub trust: Trust,
+    /// Certificate presented to servers that authenticate clients.
+    pub identity: Option<ClientIdentity>,
+    /// Reply expected to a stream ping; defaults to [`PONG`].
+    pub expected_reply: Option<Vec<u8>>,
+    /// TLS sessions to resume and to store the session of this connection in; by
+    /// default every connection runs a full handshake.
+    pub sessions: Option<SessionCache>,
+    /// File the TLS secrets of the connection are logged to, for decrypting captures.
+    pub key_log: Option<KeyLog>,
+    /// Directory the qlog trace of the connection is written to.
+    pub qlog_dir: Option<PathBuf>,
+}
+
+/// A WebTransport session to a ping-pong server.
+pub struct PingClient {
+    endpoint: Endpoint<endpoint_side::Client>,
+    connection: Connection,
+    target: Target,
+    next_seq: AtomicU64,
+    /// Taken by a unidirectional ping while it waits for the server's stream.
+    uni_turn: Mutex<()>,
+    timeouts: Timeouts,
+    overall: Option<tokio::time::Instant>,
+    expected_reply: Vec<u8>,
+    timings: ConnectTimings,
+    qlog: Option<Trace>,
+}
+
+impl PingClient {
+    /// Resolves `url` and establishes a WebTransport session with the server.
+    ///
+    /// The overall deadline of [`ClientOptions::timeouts`] starts here and bounds
+    /// every later ping of this client.
+    pub async fn connect(url: &Url, options: &ClientOptions) -> Result<Self, ClientError> {
+        Self::establish(url, options).instrument(info_span!("connect", %url)).await
+    }
+
+    async fn establish(url: &Url, options: &ClientOptions) -> Result<Self, ClientError> {
+        let timeouts = options.timeouts;
+        let overall = timeouts::deadline(timeouts.overall);
+        let connect_deadline = timeouts::deadline(timeouts.connect);
+        let progress = Arc::new(HandshakeProgress::default());
+        let mut timings = ConnectTimings::default();
+        let start = Instant::now();
+
+        let setup = async {
+            let target = Target::resolve(url, options.server_name.as_deref(), options.ip_version).await?;
+
+            let tls_config = tls::client_config(&target.url, &options.trust, options.identity.as_ref(),
+                                                options.key_log.as_ref(), options.sessions.as_ref(),
+                                                &progress)?;
+
+            let qlog = match &options.qlog_dir {
+                Some(dir) => Some(Trace::create(dir, start)?),
+                None => None,
+            };
+            let mut transport = TransportConfig::default();
+            if let Some(qlog) = &qlog {
+                transport.qlog_stream(qlog.packet_events());
+            }
+
+            let addr = options.bind.unwrap_or_else(|| target.default_bind_address());
+            let mut config = ClientConfig::builder()
+                .with_bind_address(addr)
+                .with_custom_tls_and_transport(tls_config, transport)
+                .dns_resolver(target.resolver())
+                .build();
+            if let Some(qlog) = &qlog {
+                let connection_id = qlog.connection_id();
+                config.quic_config_mut().initial_dst_cid_provider(Arc::new(move || connection_id));
+            }
+
+            let endpoint = match Endpoint::client(config) {
+                Ok(endpoint) => endpoint,
+                Err(source) => return Err(ClientError::EndpointBind { address: addr, source }),
+            };
+            if let Some(qlog) = &qlog {
+                qlog.started(endpoint.local_addr().unwrap_or(addr), target.address);
+            }
+            Ok((endpoint, target, qlog))
+        };
+        let setup = timeouts::within(Phase::Connect, connect_deadline, overall, setup);
+        let (endpoint, target, qlog) = in_span(info_span!("bind", phase = %Phase::Connect), setup).await?;
+        timings.resolve = start.elapsed();
+
+        // wtransport runs the handshake and the session request as one future: it is in
+        // the connect phase until the server certificate is verified, then in the session phase.
+        let connection = {
+            let connecting = endpoint.connect(target.url.as_str());
+            tokio::pin!(connecting);
+            let handshake = async {
+                tokio::select! {
+                    result = &mut connecting => match result {
+                        Ok(connection) => Ok(Some(connection)),
+                        Err(e) => Err(ClientError::connecting(e, Phase::Connect)),
+                    },
+                    _ = progress.certificate_verified.notified() => Ok(None),
+                }
+            };
+            let handshake = timeouts::within(Phase::Connect, connect_deadline, overall, handshake);
+            let handshake = in_span(info_span!("handshake", phase = %Phase::Connect), handshake).await?;
+            timings.handshake = start.elapsed() - timings.resolve;
+            match handshake {
+                Some(connection) => {
+                    timings.resumed = true;
+                    connection
+                }
+                None => {
+                    if let Some(qlog) = &qlog {
+                        qlog.certificate_verified(timings.resolve + timings.handshake);
+                    }
+                    let session = async {
+                        match (&mut connecting).await {
+                            Ok(connection) => Ok(connection),
+                            Err(e) => Err(ClientError::connecting(e, Phase::Session)),
+                        }
+                    };
+                    let session_deadline = timeouts::deadline(timeouts.session);
+                    let session = timeouts::within(Phase::Session, session_deadline, overall, session);
+                    let connection = in_span(info_span!("session", phase = %Phase::Session), session).await?;
+                    timings.session = start.elapsed() - timings.resolve - timings.handshake;
+                    connection
+                }
+            }
+        };
+        if let Some(zero_rtt) = progress.offered_session() {
+            timings.resumption_offered = true;
+            timings.zero_rtt_allowed = zero_rtt;
+        }
+
+        if let Some(qlog) = &qlog {
+            qlog.session_established(timings.total(), timings.resumed);
+        }
+        info!(target = %target, elapsed = ?timings.total(), resumed = timings.resumed, "connected");
+
+        let expected_reply = options.expected_reply.clone().unwrap_or_else(|| PONG.to_vec());
+        Ok(PingClient {
+            endpoint,
+            connection,
+            target,
+            next_seq: AtomicU64::new(1),
+            uni_turn: Mutex::new(()),
+            timeouts,
+            overall,
+            expected_reply,
+            timings,
+            qlog,
+        })
+    }
+
+    /// The server this client is connected to.
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    /// Deadlines this client was connected with.
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// Instant the overall deadline expires, if there is one.
+    pub fn overall_deadline(&self) -> Option<tokio::time::Instant> {
+        self.overall
+    }
+
+    /// How long connecting this client took, step by step.
+    pub fn connect_timings(&self) -> ConnectTimings {
+        self.timings
+    }
+
+    /// Reply a stream ping must be answered with.
+    pub fn expected_reply(&self) -> &[u8] {
+        &self.expected_reply
+    }
+
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Error the connection was lost with, once it is closed: by the server, by a
+    /// transport error or after the QUIC idle timeout without any packet from the server.
+    pub fn lost(&self) -> Option<ClientError> {
+        let reason = self.connection.quic_connection().close_reason()?;
+        Some(ClientError::ConnectionLost(reason.into()))
+    }
+
+    /// File the qlog trace of the connection is written to, if it is traced.
+    pub fn qlog_path(&self) -> Option<&Path> {
+        self.qlog.as_ref().map(|qlog| qlog.path())
+    }
+
+    /// Replaces a stream error caused by the connection closing with the reason it
+    /// closed, such as the code and reason of the server.
+    fn closed_or(&self, error: ClientError) -> ClientError {
+        match (&error, self.lost()) {
+            (ClientError::StreamOpen(_) | ClientError::Write(_) | ClientError::Read(_), Some(lost)) => lost,
+            _ => error,
+        }
+    }
+
+    /// Local address the client endpoint is bound to.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.endpoint.local_addr().ok()
+    }
+
+    /// Sends a `ping` on a new bidirectional stream and returns the round-trip time
+    /// until the expected reply, `pong` by default, arrived.
+    pub async fn ping(&self) -> Result<Pong, ClientError> {
+        let ping = self.ping_stream().instrument(info_span!("ping", stream_id = field::Empty)).await;
+        ping.map_err(|e| self.closed_or(e))
+    }
+
+    async fn ping_stream(&self) -> Result<Pong, ClientError> {
+        let start = Instant::now();
+
+        let open = self.within(Phase::StreamOpen, open_bi(&self.connection));
+        let mut stream = in_span(info_span!("stream_open", phase = %Phase::StreamOpen), open).await?;
+        let stream_id = stream.0.id().into_u64();
+        Span::current().record("stream_id", stream_id);
+
+        // The reply deadline covers writing the ping and reading the reply.
+        let deadline = timeouts::deadline(self.timeouts.get(Phase::Reply));
+        let write = async {
+            match stream.0.write_all(PING).await {
+                Ok(_) => finish(&mut stream.0).await,
+                Err(e) => Err(ClientError::Write(e)),
+            }
+        };
+        let write = timeouts::within(Phase::Reply, deadline, self.overall, write);
+        in_span(info_span!("write", phase = %Phase::Reply), write).await?;
+        if let Some(qlog) = &self.qlog {
+            qlog.stream_sent(stream_id, PING.len());
+        }
+
+        let read = async {
+            let mut reply = read_reply(&mut stream.1, self.expected_reply.len()).await?;
+            let rtt = start.elapsed();
+            let until = [deadline, self.overall].into_iter().flatten().min();
+            read_reply_end(&mut stream.1, &mut reply, self.expected_reply.len(), until).await?;
+            Ok((reply, rtt))
+        };
+        let read = timeouts::within(Phase::Reply, deadline, self.overall, read);
+        let (reply, rtt) = in_span(info_span!("read", phase = %Phase::Reply), read).await?;
+        if let Some(qlog) = &self.qlog {
+            qlog.stream_received(stream_id, reply.len());
+        }
+        if reply != self.expected_reply {
+            let error = ClientError::ProtocolMismatch { expected: self.expected_reply.clone(), received: reply };
+            info!(error = %error, "failed");
+            return Err(error);
+        }
+        info!(rtt = ?rtt, "pong");
+        Ok(Pong { stream_id, rtt })
+    }
+
+    /// Sends `ping:<seq>` on a new unidirectional stream and returns the round-trip time
+    /// until the server opened a unidirectional stream of its own carrying `pong:<seq>`.
+    ///
+    /// Replies late for earlier pings are skipped; any other reply fails with
+    /// [`ClientError::ProtocolMismatch`]. Only their content tells the server's streams
+    /// apart, so the unidirectional pings of a client take turns.
+    pub async fn ping_uni(&self) -> Result<Pong, ClientError> {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let span = info_span!("ping_uni", seq, stream_id = field::Empty);
+        let ping = self.ping_uni_seq(seq).instrument(span).await;
+        ping.map_err(|e| self.closed_or(e))
+    }
+
+    async fn ping_uni_seq(&self, seq: u64) -> Result<Pong, ClientError> {
+        let _turn = self.uni_turn.lock().await;
+        let start = Instant::now();
+
+        let open = self.within(Phase::StreamOpen, open_uni(&self.connection));
+        let mut send = in_span(info_span!("stream_open", phase = %Phase::StreamOpen), open).await?;
+        let stream_id = send.id().into_u64();
+        Span::current().record("stream_id", stream_id);
+
+        let ping = datagram::encode(seq);
+        let deadline = timeouts::deadline(self.timeouts.get(Phase::Reply));
+        let write = async {
+            match send.write_all(&ping).await {
+                Ok(_) => finish(&mut send).await,
+                Err(e) => Err(ClientError::Write(e)),
+            }
+        };
+        let write = timeouts::within(Phase::Reply, deadline, self.overall, write);
+        in_span(info_span!("write", phase = %Phase::Reply), write).await?;
+        if let Some(qlog) = &self.qlog {
+            qlog.stream_sent(stream_id, ping.len());
+        }
+
+        let expected = [PONG, &ping[PING.len()..]].concat();
+        let read = async {
+            loop {
+                let mut recv = match self.connection.accept_uni().await {
+                    Ok(recv) => recv,
+                    Err(e) => return Err(ClientError::ConnectionLost(e)),
+                };
+                let mut reply = read_reply(&mut recv, expected.len()).await?;
+                let rtt = start.elapsed();
+                if let Some(qlog) = &self.qlog {
+                    qlog.stream_received(recv.id().into_u64(), reply.len());
+                }
+                match answered_seq(&reply) {
+                    Some(answered) if answered < seq => debug!(answered, "late pong"),
+                    _ => {
+                        let until = [deadline, self.overall].into_iter().flatten().min();
+                        read_reply_end(&mut recv, &mut reply, expected.len(), until).await?;
+                        return Ok((reply, rtt));
+                    }
+                }
+            }
+        };
+        let read = timeouts::within(Phase::Reply, deadline, self.overall, read);
+        let (reply, rtt) = in_span(info_span!("read", phase = %Phase::Reply), read).await?;
+        if reply != expected {
+            let error = ClientError::ProtocolMismatch { expected, received: reply };
+            info!(error = %error, "failed");
+            return Err(error);
+        }
+        info!(rtt = ?rtt, "pong");
+        Ok(Pong { stream_id, rtt })
+    }
+
+    /// Sends a sequence-numbered datagram ping and returns the round-trip time until
+    /// its echo arrived, ignoring echoes of other pings.
+    ///
+    /// Datagrams are unreliable: if no echo arrives before the reply deadline the
+    /// ping is considered lost and [`ClientError::TimeOut`] is returned.
+    pub async fn ping_datagram(&self) -> Result<Duration, ClientError> {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        in_span(info_span!("ping_datagram", seq), self.ping_datagram_seq(seq)).await
+    }
+
+    async fn ping_datagram_seq(&self, seq: u64) -> Result<Duration, ClientError> {
+        let start = Instant::now();
+        self.send_datagram(seq)?;
+
+        let echo = async {
+            loop {
+                let payload = self.receive_datagram().await?;
+                if datagram::decode(&payload) == Some(seq) {
+                    let rtt = start.elapsed();
+                    info!(rtt = ?rtt, "echo");
+                    return Ok(rtt);
+                }
+            }
+        };
+        self.within(Phase::Reply, echo).await
+    }
+
+    /// Sends the datagram ping with sequence number `seq` without waiting for its echo.
+    pub fn send_datagram(&self, seq: u64) -> Result<(), ClientError> {
+        let payload = datagram::encode(seq);
+        let length = payload.len();
+        match self.connection.send_datagram(payload) {
+            Ok(()) => {
+                if let Some(qlog) = &self.qlog {
+                    qlog.datagram_sent(length);
+                }
+                Ok(())
+            }
+            Err(e) => Err(ClientError::DatagramSend(e)),
+        }
+    }
+
+    /// Waits for the next datagram from the server and returns its payload.
+    pub async fn receive_datagram(&self) -> Result<Vec<u8>, ClientError> {
+        match self.connection.receive_datagram().await {
+            Ok(datagram) => {
+                if let Some(qlog) = &self.qlog {
+                    qlog.datagram_received(datagram.payload().len());
+                }
+                Ok(datagram.payload().to_vec())
+            }
+            Err(e) => Err(ClientError::ConnectionLost(e)),
+        }
+    }
+
+    /// Runs a throughput test, calling `on_interval` with each interval report.
+    pub async fn throughput(&self,
+                            options: &BulkOptions,
+                            on_interval: impl FnMut(&Interval)) -> Result<BulkReport, ClientError> {
+        self.throughput_until(options, on_interval, future::pending()).await
+    }
+
+    /// Runs a throughput test like [`PingClient::throughput`] that stops sending once
+    /// `stop` completes, reporting on the data sent until then.
+    pub async fn throughput_until(&self,
+                                  options: &BulkOptions,
+                                  on_interval: impl FnMut(&Interval),
+                                  stop: impl Future<Output = ()>) -> Result<BulkReport, ClientError> {
+        let test = throughput::run(&self.connection, options, self.timeouts, on_interval, stop);
+        in_span(info_span!("throughput", phase = %Phase::Overall), timeouts::within(Phase::Overall, None, self.overall, test)).await
+    }
+
+    /// Closes the session normally, with [`CLOSE_NORMAL`].
+    pub async fn close(self) -> Closed {
+        self.close_with(CLOSE_NORMAL, "").await
+    }
+
+    /// Closes the session with the application error `code` and `reason`, then waits
+    /// up to the close deadline for the connection to finish closing, so that the
+    /// server gets the close instead of timing the connection out.
+    ///
+    /// Nothing is sent if the server closed the session first or the connection was
+    /// lost; that is reported instead.
+    pub async fn close_with(self, code: u32, reason: &str) -> Closed {
+        let span = info_span!("close", target = %self.target, code);
+        self.shut_down(code, reason).instrument(span).await
+    }
+
+    async fn shut_down(self, code: u32, reason: &str) -> Closed {
+        let closed = match self.connection.quic_connection().close_reason().map(ConnectionError::from) {
+            Some(ConnectionError::ApplicationClosed(close)) => {
+                let reason = String::from_utf8_lossy(close.reason()).into_owned();
+                if let Some(qlog) = &self.qlog {
+                    qlog.closed(&self.connection, Owner::Remote, close.code().into_inner(), &reason);
+                }
+                Closed::ByServer { code: close.code().into_inner(), reason }
+            }
+            Some(error) => {
+                if let Some(qlog) = &self.qlog {
+                    qlog.closed(&self.connection, Owner::Local, 0, &error.to_string());
+                }
+                Closed::Lost(error)
+            }
+            None => {
+                self.connection.close(VarInt::from_u32(code), reason.as_bytes());
+                if let Some(qlog) = &self.qlog {
+                    qlog.closed(&self.connection, Owner::Local, code.into(), reason);
+                }
+                let drain = async {
+                    self.endpoint.wait_idle().await;
+                    Ok(())
+                };
+                let deadline = timeouts::deadline(self.timeouts.close);
+                let drained = timeouts::within(Phase::Close, deadline, None, drain).await.is_ok();
+                Closed::ByClient { code, reason: reason.to_string(), drained }
+            }
+        };
+        debug!(%closed, "closed");
+        closed
+    }
+
+    /// Runs `future` under the deadline of `phase` and the overall deadline.
+    async fn within<T, F>(&self, phase: Phase, future: F) -> Result<T, ClientError>
+    where
+        F: Future<Output = Result<T, ClientError>>,
+    {
+        let deadline = timeouts::deadline(self.timeouts.get(phase));
+        timeouts::within(phase, deadline, self.overall, future).await
+    }
+}
+
+/// Runs `future` in `span` and logs there how long it took and whether it failed,
+/// so that the log shows which phase was in progress when a probe failed.
+///
+/// The time is measured here rather than from the span: quinn's connection tasks
+/// are spawned in it and keep it open.
+async fn in_span<T, F>(span: Span, future: F) -> Result<T, ClientError>
+where
+    F: Future<Output = Result<T, ClientError>>,
+{
+    let traced = async {
+        let start = Instant::now();
+        let result = future.await;
+        match &result {
+            Ok(_) => debug!(elapsed = ?start.elapsed(), "done"),
+            Err(e) => info!(elapsed = ?start.elapsed(), error = %e, "failed"),
+        }
+        result
+    };
+    traced.instrument(span).await
+}
+
+/// Opens a bidirectional stream on `connection`.
+pub(crate) async fn open_bi(connection: &Connection) -> Result<(SendStream, RecvStream), ClientError> {
+    match connection.open_bi().await {
+        Ok(opening) => match opening.await {
+            Ok(stream) => Ok(stream),
+            Err(e) => Err(ClientError::StreamOpen(e)),
+        },
+        Err(e) => Err(ClientError::ConnectionLost(e)),
+    }
+}
+
+/// Opens a unidirectional stream on `connection`.
+pub(crate) async fn open_uni(connection: &Connection) -> Result<SendStream, ClientError> {
+    match connection.open_uni().await {
+        Ok(opening) => match opening.await {
+            Ok(send) => Ok(send),
+            Err(e) => Err(ClientError::StreamOpen(e)),
+        },
+        Err(e) => Err(ClientError::ConnectionLost(e)),
+    }
+}
+
+/// Finishes the sending side of a stream.
+pub(crate) async fn finish(send: &mut SendStream) -> Result<(), ClientError> {
+    match send.finish().await {
+        Ok(()) => Ok(()),
+        Err(e) => Err(ClientError::Write(e)),
+    }
+}
+
+/// Sequence number of the ping a unidirectional `pong:<seq>` answers.
+fn answered_seq(reply: &[u8]) -> Option<u64> {
+    let digits = reply.strip_prefix(PONG)?.strip_prefix(b":")?;
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Reads the server reply from the receive half of the stream.
+///
+/// Reading stops at end-of-stream or as soon as `expected_len` bytes have been
+/// received: the reference server never finishes its side of the stream, so
+/// waiting for end-of-stream alone would block forever. [`read_reply_end`] then
+/// checks that nothing follows.
+pub(crate) async fn read_reply(stream: &mut RecvStream, expected_len: usize) -> Result<Vec<u8>, ClientError> {
+    let mut reply = Vec::with_capacity(expected_len);
+    let mut buf = [0u8; 64];
+
+    while reply.len() < expected_len {
+        match stream.read(&mut buf).await {
+            Ok(Some(n)) => reply.extend_from_slice(&buf[..n]),
+            Ok(None) => break,
+            Err(e) => return Err(ClientError::Read(e)),
+        }
+    }
+    Ok(reply)
+}
+
+/// Waits for the end of the stream after a `reply` of `expected_len` bytes, appending
+/// any data that follows so that the reply no longer matches.
+///
+/// Waits at most [`REPLY_END_WAIT`] and never past `until`: a stream still open
+/// then, as the reference server leaves it, is taken to end with the reply. A reply
+/// of another length already ended or already mismatches, and is left as is.
+pub(crate) async fn read_reply_end(stream: &mut RecvStream,
+                                   reply: &mut Vec<u8>,
+                                   expected_len: usize,
+                                   until: Option<time::Instant>) -> Result<(), ClientError> {
+    if reply.len() != expected_len {
+        return Ok(());
+    }
+    let wait = time::Instant::now() + REPLY_END_WAIT;
+    let until = until.map_or(wait, |until| until.min(wait));
+    let mut buf = [0u8; 64];
+    match time::timeout_at(until, stream.read(&mut buf)).await {
+        Ok(Ok(Some(n))) => {
+            reply.extend_from_slice(&buf[..n]);
+            Ok(())
+        }
+        Ok(Ok(None)) | Err(_) => Ok(()),
+        Ok(Err(e)) => Err(ClientError::Read(e)),
+    }
+}
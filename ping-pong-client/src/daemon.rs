@@ -0,0 +1,124 @@
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use tiny_http::Header;
+use tiny_http::Response;
+use tiny_http::Server;
+use tokio::task;
+use tokio::task::JoinSet;
+use tokio::time;
+use tokio::time::MissedTickBehavior;
+use url::Url;
+
+use ping_pong_client::metrics::Metrics;
+use ping_pong_client::reconnect::Backoff;
+use ping_pong_client::ClientOptions;
+use ping_pong_client::ErrorKind;
+use ping_pong_client::PingClient;
+
+use crate::shutdown::Shutdown;
+
+/// Probes every target each `interval` and serves the results on `server` until
+/// shutdown, then waits for the probes in flight and closes their connections.
+/// `interval` must be more than zero, which `--interval` already ensures.
+pub async fn run(server: Server,
+                 urls: &[Url],
+                 options: &ClientOptions,
+                 interval: Duration,
+                 backoff: Backoff,
+                 shutdown: Shutdown) {
+    let metrics = Arc::new(Metrics::new());
+    let mut probes = JoinSet::new();
+    for url in urls {
+        metrics.add_target(url.as_str());
+        probes.spawn(probe(url.clone(), options.clone(), interval, backoff, metrics.clone(), shutdown.clone()));
+    }
+    let server = Arc::new(server);
+    let serving = {
+        let server = server.clone();
+        task::spawn_blocking(move || serve_metrics(&server, &metrics))
+    };
+
+    while probes.join_next().await.is_some() {}
+    server.unblock();
+    let _ = serving.await;
+}
+
+/// Pings `url` over a stream and with a datagram every `interval`, keeping the
+/// connection between probes and reconnecting once it is lost. Failed connection
+/// attempts are retried on the first tick after the delay of `backoff`. Stops on
+/// `shutdown`, closing the connection.
+async fn probe(url: Url,
+               options: ClientOptions,
+               interval: Duration,
+               backoff: Backoff,
+               metrics: Arc<Metrics>,
+               mut shutdown: Shutdown) {
+    let target = url.as_str();
+    let mut ticker = time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut connection: Option<PingClient> = None;
+    let mut failures = 0;
+    let mut retry_at = Instant::now();
+    let mut lost_at: Option<Instant> = None;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.requested() => break,
+            _ = ticker.tick() => {}
+        }
+        let client = match connection.take() {
+            Some(client) => client,
+            None if Instant::now() < retry_at => continue,
+            None => {
+                let start = Instant::now();
+                match PingClient::connect(&url, &options).await {
+                    Ok(client) => {
+                        metrics.connected(target, start.elapsed());
+                        if let Some(lost_at) = lost_at.take() {
+                            metrics.outage(target, lost_at.elapsed());
+                        }
+                        failures = 0;
+                        client
+                    }
+                    Err(e) => {
+                        metrics.connect_failed(target, e.kind());
+                        retry_at = Instant::now() + backoff.delay(failures);
+                        failures = failures.saturating_add(1);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let stream = client.ping().await.map(|pong| pong.rtt).map_err(|e| e.kind());
+        metrics.stream_ping(target, stream);
+        let datagram = client.ping_datagram().await.map_err(|e| e.kind());
+        metrics.datagram_ping(target, datagram);
+
+        if stream == Err(ErrorKind::Unreachable) || datagram == Err(ErrorKind::Unreachable) {
+            metrics.disconnected(target);
+            lost_at = Some(Instant::now());
+            client.close().await;
+        } else {
+            connection = Some(client);
+        }
+    }
+    if let Some(client) = connection {
+        client.close().await;
+    }
+}
+
+/// Answers `/metrics` with the rendered `metrics`, anything else with 404, until
+/// `server` is unblocked.
+fn serve_metrics(server: &Server, metrics: &Metrics) {
+    let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").expect("valid header");
+    for request in server.incoming_requests() {
+        let response = match request.url() {
+            "/metrics" => Response::from_string(metrics.render()).with_header(content_type.clone()),
+            _ => Response::from_string("Not Found").with_status_code(404),
+        };
+        let _ = request.respond(response);
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::stats::Stats;
+
+/// Prefix of every datagram ping; the sequence number follows in decimal.
+const PREFIX: &[u8] = b"ping:";
+
+/// Encodes the payload of the datagram ping with sequence number `seq`.
+pub fn encode(seq: u64) -> Vec<u8> {
+    let mut payload = PREFIX.to_vec();
+    payload.extend_from_slice(seq.to_string().as_bytes());
+    payload
+}
+
+/// Decodes the sequence number of an echoed datagram ping.
+pub fn decode(payload: &[u8]) -> Option<u64> {
+    let digits = payload.strip_prefix(PREFIX)?;
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Classification of an echoed datagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Echo {
+    /// First echo of a ping, in order.
+    Reply { seq: u64, rtt: Duration },
+    /// First echo of a ping, arriving after the echo of a later ping.
+    Reordered { seq: u64, rtt: Duration },
+    /// Another echo of a ping that had already been answered.
+    Duplicate { seq: u64 },
+    /// Payload that does not answer any ping sent in this run.
+    Unknown,
+}
+
+/// Matches echoed datagrams to the pings they answer.
+#[derive(Debug, Default)]
+pub struct DatagramTracker {
+    sent: HashMap<u64, Instant>,
+    received: HashSet<u64>,
+    highest: u64,
+    stats: Stats,
+}
+
+impl DatagramTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that ping `seq` was sent at `at`.
+    pub fn on_sent(&mut self, seq: u64, at: Instant) {
+        self.sent.insert(seq, at);
+        self.stats.record_sent();
+    }
+
+    /// Records an echoed `payload` received at `at`.
+    pub fn on_echo(&mut self, payload: &[u8], at: Instant) -> Echo {
+        let Some(seq) = decode(payload) else {
+            return Echo::Unknown;
+        };
+        let Some(sent_at) = self.sent.get(&seq) else {
+            return Echo::Unknown;
+        };
+
+        if !self.received.insert(seq) {
+            self.stats.record_duplicate();
+            return Echo::Duplicate { seq };
+        }
+
+        let rtt = at.saturating_duration_since(*sent_at);
+        self.stats.record_reply(rtt);
+
+        if seq < self.highest {
+            self.stats.record_reordered();
+            Echo::Reordered { seq, rtt }
+        } else {
+            self.highest = seq;
+            Echo::Reply { seq, rtt }
+        }
+    }
+
+    /// Whether every ping sent so far has been answered.
+    pub fn is_complete(&self) -> bool {
+        self.received.len() == self.sent.len()
+    }
+
+    /// Sequence numbers of the pings not answered so far, in ascending order.
+    pub fn unanswered(&self) -> Vec<u64> {
+        let mut seqs: Vec<u64> = self.sent.keys().filter(|seq| !self.received.contains(seq)).copied().collect();
+        seqs.sort_unstable();
+        seqs
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn into_stats(self) -> Stats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_round_trip() {
+        assert_eq!(decode(&encode(42)), Some(42));
+        assert_eq!(decode(b"pong:1"), None);
+        assert_eq!(decode(b"ping:x"), None);
+    }
+
+    #[test]
+    fn classifies_echoes() {
+        let start = Instant::now();
+        let mut tracker = DatagramTracker::new();
+        for seq in 1..=3 {
+            tracker.on_sent(seq, start);
+        }
+
+        let at = start + Duration::from_millis(5);
+        assert_eq!(tracker.on_echo(&encode(1), at), Echo::Reply { seq: 1, rtt: Duration::from_millis(5) });
+        assert_eq!(tracker.on_echo(&encode(3), at), Echo::Reply { seq: 3, rtt: Duration::from_millis(5) });
+        assert_eq!(tracker.on_echo(&encode(2), at), Echo::Reordered { seq: 2, rtt: Duration::from_millis(5) });
+        assert_eq!(tracker.on_echo(&encode(2), at), Echo::Duplicate { seq: 2 });
+        assert_eq!(tracker.on_echo(&encode(9), at), Echo::Unknown);
+        assert!(tracker.is_complete());
+        assert!(tracker.unanswered().is_empty());
+
+        let stats = tracker.into_stats();
+        assert_eq!(stats.received(), 3);
+        assert_eq!(stats.loss_percent(), 0.0);
+    }
+}
@@ -1,25 +1,298 @@
 use std::error::Error;
 use std::fmt;
-use std::fmt::Debug;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use wtransport::error::ConnectingError;
 use wtransport::error::ConnectionError;
+use wtransport::error::SendDatagramError;
+use wtransport::error::StreamOpeningError;
+use wtransport::error::StreamReadError;
+use wtransport::error::StreamWriteError;
+
+use crate::timeouts::Phase;
 
+/// Failure of the ping-pong client, one variant per stage of a probe.
 #[derive(Debug)]
 pub enum ClientError {
-    TimeOut,
-    LocallyClosed,
-    QuicError,
-    StreamOpeningError
+    /// The local UDP endpoint could not be bound.
+    EndpointBind { address: SocketAddr, source: io::Error },
+    /// The fleet configuration file could not be read or is invalid.
+    Config { path: PathBuf, source: io::Error },
+    /// The TCP listener of the metrics endpoint could not be bound.
+    MetricsListen { address: SocketAddr, source: io::Error },
+    /// The CA certificates to verify the server against could not be loaded, from
+    /// `path` or from the platform when there is none.
+    TrustStore { path: Option<PathBuf>, source: io::Error },
+    /// The client certificate or private key could not be loaded from `path`.
+    IdentityFile { path: PathBuf, source: io::Error },
+    /// The TLS key log file could not be opened.
+    KeyLogFile { path: PathBuf, source: io::Error },
+    /// The qlog trace of a connection could not be created at `path`.
+    QlogFile { path: PathBuf, source: io::Error },
+    /// The host of the URL did not resolve to an address of the requested family.
+    Dns { host: String, source: Option<io::Error> },
+    /// The QUIC connection to the server could not be established.
+    Connect(ConnectingError),
+    /// The TLS handshake with the server failed.
+    TlsHandshake(ConnectionError),
+    /// The server refused the client certificate, or required one that was not given.
+    ClientCertificateRejected(ConnectionError),
+    /// The server refused the WebTransport session request.
+    SessionRejected,
+    /// The connection was closed or lost after the session was established.
+    ConnectionLost(ConnectionError),
+    /// A stream could not be opened on the session.
+    StreamOpen(StreamOpeningError),
+    /// The ping could not be written to the stream.
+    Write(StreamWriteError),
+    /// The data of a throughput test could not be written to its stream.
+    BulkWrite(StreamWriteError),
+    /// The reply could not be read from the stream.
+    Read(StreamReadError),
+    /// A datagram ping could not be sent.
+    DatagramSend(SendDatagramError),
+    /// The server answered with something other than the expected reply.
+    ProtocolMismatch { expected: Vec<u8>, received: Vec<u8> },
+    /// The deadline of a phase expired.
+    TimeOut(Phase),
+}
+
+/// Class of a [`ClientError`], used for exit codes and reporting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorKind {
+    /// The local endpoint could not be set up.
+    Local,
+    /// The server host could not be resolved.
+    Dns,
+    /// The server could not be reached or the connection was lost.
+    Unreachable,
+    /// The TLS handshake failed.
+    Tls,
+    /// The server refused the WebTransport session.
+    Rejected,
+    /// A stream or datagram operation failed on an established session.
+    Transport,
+    /// The server answered with an unexpected reply.
+    Protocol,
+    /// The server did not answer in time.
+    Timeout,
+}
+
+impl ErrorKind {
+    /// Process exit code reported for this class of failure.
+    ///
+    /// 0 is success and 2 is left to command-line usage errors.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::Local => 3,
+            ErrorKind::Dns => 4,
+            ErrorKind::Unreachable => 5,
+            ErrorKind::Tls => 6,
+            ErrorKind::Rejected => 7,
+            ErrorKind::Transport => 8,
+            ErrorKind::Protocol => 9,
+            ErrorKind::Timeout => 10,
+        }
+    }
+
+    /// Stable lower-case name of the class.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Local => "local",
+            ErrorKind::Dns => "dns",
+            ErrorKind::Unreachable => "unreachable",
+            ErrorKind::Tls => "tls",
+            ErrorKind::Rejected => "rejected",
+            ErrorKind::Transport => "transport",
+            ErrorKind::Protocol => "protocol",
+            ErrorKind::Timeout => "timeout",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ClientError {
+    /// Class of this failure.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ClientError::EndpointBind { .. }
+            | ClientError::MetricsListen { .. }
+            | ClientError::Config { .. }
+            | ClientError::TrustStore { .. }
+            | ClientError::IdentityFile { .. }
+            | ClientError::KeyLogFile { .. }
+            | ClientError::QlogFile { .. } => ErrorKind::Local,
+            ClientError::Dns { .. } => ErrorKind::Dns,
+            ClientError::Connect(_) | ClientError::ConnectionLost(_) => ErrorKind::Unreachable,
+            ClientError::TlsHandshake(_) | ClientError::ClientCertificateRejected(_) => ErrorKind::Tls,
+            ClientError::SessionRejected => ErrorKind::Rejected,
+            ClientError::StreamOpen(_)
+            | ClientError::Write(_)
+            | ClientError::BulkWrite(_)
+            | ClientError::Read(_)
+            | ClientError::DatagramSend(_) => ErrorKind::Transport,
+            ClientError::ProtocolMismatch { .. } => ErrorKind::Protocol,
+            ClientError::TimeOut(_) => ErrorKind::Timeout,
+        }
+    }
+
+    /// Classifies a failed [`wtransport::Endpoint::connect`] that was in `phase`.
+    pub(crate) fn connecting(error: ConnectingError, phase: Phase) -> Self {
+        match error {
+            ConnectingError::SessionRejected => ClientError::SessionRejected,
+            ConnectingError::ConnectionError(ConnectionError::TimedOut) => ClientError::TimeOut(phase),
+            ConnectingError::ConnectionError(e) if rejects_client_certificate(&e) => {
+                ClientError::ClientCertificateRejected(e)
+            }
+            ConnectingError::ConnectionError(e) if is_tls_failure(&e) => ClientError::TlsHandshake(e),
+            e => ClientError::Connect(e),
+        }
+    }
+}
+
+/// Whether the server closed the connection with a TLS alert about the client
+/// certificate: bad, unsupported, revoked, expired, unknown, from an unknown CA,
+/// denied access, or missing.
+fn rejects_client_certificate(error: &ConnectionError) -> bool {
+    let close = match error {
+        ConnectionError::ConnectionClosed(close) => close.to_string(),
+        _ => return false,
+    };
+    close
+        .strip_prefix("the cryptographic handshake failed: error ")
+        .and_then(|rest| rest.split(':').next())
+        .and_then(|alert| alert.trim().parse::<u8>().ok())
+        .is_some_and(|alert| matches!(alert, 42..=46 | 48 | 49 | 116))
+}
+
+/// Whether the connection was closed with a TLS alert, by either side.
+///
+/// wtransport does not expose the QUIC transport error code, only its rendering, so
+/// the crypto error range (0x100-0x1ff) is recognised from the message.
+fn is_tls_failure(error: &ConnectionError) -> bool {
+    match error {
+        ConnectionError::ConnectionClosed(close) => {
+            close.to_string().starts_with("the cryptographic handshake failed")
+        }
+        ConnectionError::QuicProto(e) => {
+            let message = e.to_string();
+            message
+                .rsplit_once("(code: ")
+                .and_then(|(_, code)| code.trim_end_matches(')').parse::<u64>().ok())
+                .is_some_and(|code| (0x100..0x200).contains(&code))
+        }
+        _ => false,
+    }
 }
 
 impl fmt::Display for ClientError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ClientError::TimeOut => write!(f, "{:?}", ConnectionError::TimedOut),
-            ClientError::LocallyClosed => write!(f, "{:?}", ConnectionError::LocallyClosed),
-            ClientError::QuicError => write!(f, "{:?}", ConnectionError::QuicError),
-            ClientError::StreamOpeningError => write!(f, "{:?}", "Failed to open stream")
+            ClientError::EndpointBind { address, source } => {
+                write!(f, "cannot bind local address {}: {}; check --bind or free the port",
+                       address, source)
+            }
+            ClientError::Config { path, source } => {
+                write!(f, "cannot load the configuration from {}: {}; check --config", path.display(), source)
+            }
+            ClientError::MetricsListen { address, source } => {
+                write!(f, "cannot listen for metrics on {}: {}; check --metrics or free the port",
+                       address, source)
+            }
+            ClientError::TrustStore { path: Some(path), source } => {
+                write!(f, "cannot load CA certificates from {}: {}; check --ca-file",
+                       path.display(), source)
+            }
+            ClientError::TrustStore { path: None, source } => {
+                write!(f, "cannot load the system CA certificates: {}; use --ca-file or --pin", source)
+            }
+            ClientError::IdentityFile { path, source } => {
+                write!(f, "cannot load the client identity from {}: {}; check --cert and --key",
+                       path.display(), source)
+            }
+            ClientError::KeyLogFile { path, source } => {
+                write!(f, "cannot open the key log file {}: {}; check --keylog or SSLKEYLOGFILE",
+                       path.display(), source)
+            }
+            ClientError::QlogFile { path, source } => {
+                write!(f, "cannot create the qlog trace {}: {}; check --qlog-dir", path.display(), source)
+            }
+            ClientError::Dns { host, source: Some(source) } => {
+                write!(f, "cannot resolve host '{}': {}; check the URL", host, source)
+            }
+            ClientError::Dns { host, source: None } => {
+                write!(f, "host '{}' has no address of the requested IP version; try without -4/-6",
+                       host)
+            }
+            ClientError::Connect(e) => {
+                write!(f, "cannot connect to the server: {}; check that it is running and reachable", e)
+            }
+            ClientError::TlsHandshake(e) => {
+                write!(f, "TLS handshake failed: {}; check the server certificate, --server-name \
+                           and --ca-file or --pin", e)
+            }
+            ClientError::ClientCertificateRejected(e) => {
+                write!(f, "the server rejected the client certificate: {}; check --cert and --key", e)
+            }
+            ClientError::SessionRejected => {
+                write!(f, "the server rejected the WebTransport session; check the URL path")
+            }
+            ClientError::ConnectionLost(e) => write!(f, "connection lost: {}", e),
+            ClientError::StreamOpen(e) => write!(f, "cannot open a stream: {}", e),
+            ClientError::Write(e) => write!(f, "cannot send the ping: {}", e),
+            ClientError::BulkWrite(e) => write!(f, "cannot send the throughput data: {}", e),
+            ClientError::Read(e) => write!(f, "cannot read the reply: {}", e),
+            ClientError::DatagramSend(SendDatagramError::UnsupportedByPeer) => {
+                write!(f, "the server does not accept datagrams")
+            }
+            ClientError::DatagramSend(e) => write!(f, "cannot send the datagram: {}", e),
+            ClientError::ProtocolMismatch { expected, received } if received.is_empty() => {
+                write!(f, "the server closed the stream without replying {:?}",
+                       String::from_utf8_lossy(expected))
+            }
+            ClientError::ProtocolMismatch { expected, received } => {
+                write!(f, "unexpected reply {:?}, expected {:?}",
+                       String::from_utf8_lossy(received), String::from_utf8_lossy(expected))
+            }
+            ClientError::TimeOut(Phase::Overall) => write!(f, "overall timeout expired"),
+            ClientError::TimeOut(phase) => {
+                write!(f, "{} timeout expired; check the server or raise --{}-timeout",
+                       phase, phase.as_str().replace(' ', "-"))
+            }
         }
     }
 }
 
-impl Error for ClientError {}
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClientError::EndpointBind { source, .. } => Some(source),
+            ClientError::MetricsListen { source, .. } => Some(source),
+            ClientError::Config { source, .. } => Some(source),
+            ClientError::TrustStore { source, .. } => Some(source),
+            ClientError::IdentityFile { source, .. } => Some(source),
+            ClientError::KeyLogFile { source, .. } => Some(source),
+            ClientError::QlogFile { source, .. } => Some(source),
+            ClientError::Dns { source, .. } => source.as_ref().map(|e| e as _),
+            ClientError::Connect(e) => Some(e),
+            ClientError::TlsHandshake(e) => Some(e),
+            ClientError::ClientCertificateRejected(e) => Some(e),
+            ClientError::ConnectionLost(e) => Some(e),
+            ClientError::StreamOpen(e) => Some(e),
+            ClientError::Write(e) => Some(e),
+            ClientError::BulkWrite(e) => Some(e),
+            ClientError::Read(e) => Some(e),
+            ClientError::DatagramSend(e) => Some(e),
+            ClientError::SessionRejected
+            | ClientError::ProtocolMismatch { .. }
+            | ClientError::TimeOut(_) => None,
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use wtransport::RecvStream;
+use wtransport::SendStream;
+
+use crate::client;
+use crate::errors::ClientError;
+use crate::stats::millis;
+use crate::stats::Stats;
+use crate::timeouts;
+use crate::timeouts::Phase;
+use crate::PingClient;
+
+/// Reply to one of the pings of a fan-out round.
+#[derive(Debug)]
+pub struct StreamReply {
+    /// Index of the connection in the clients the round ran on.
+    pub connection: usize,
+    /// Index of the stream on its connection.
+    pub stream: usize,
+    /// QUIC stream ID, once the stream was opened.
+    pub stream_id: Option<u64>,
+    /// Time from the common send instant of the round to the `pong`.
+    pub result: Result<Duration, ClientError>,
+}
+
+/// Replies of a fan-out round, ordered by connection and stream.
+#[derive(Debug, Default)]
+pub struct Round {
+    pub replies: Vec<StreamReply>,
+}
+
+impl Round {
+    pub fn received(&self) -> usize {
+        self.rtts().count()
+    }
+
+    pub fn fastest(&self) -> Option<Duration> {
+        self.rtts().min()
+    }
+
+    pub fn slowest(&self) -> Option<Duration> {
+        self.rtts().max()
+    }
+
+    /// How long the slowest stream waited past the fastest one. Independent streams
+    /// answer together; a wide spread means streams were held behind each other.
+    pub fn spread(&self) -> Option<Duration> {
+        Some(self.slowest()? - self.fastest()?)
+    }
+
+    fn rtts(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.replies.iter().filter_map(|reply| reply.result.as_ref().ok().copied())
+    }
+}
+
+/// Pings over `streams` new bidirectional streams on each of `clients` at once.
+///
+/// All streams are opened first, under the stream open deadline, then every ping is
+/// written at the same instant, so that the replies time how the streams are
+/// multiplexed rather than how long opening them took. The replies share one reply
+/// deadline. Servers cap the streams open at once, commonly at 100 per connection;
+/// streams beyond that wait for earlier ones to close.
+pub async fn round(clients: &[PingClient], streams: usize) -> Round {
+    let mut opening = JoinSet::new();
+    for (connection, client) in clients.iter().enumerate() {
+        let deadline = timeouts::deadline(client.timeouts().stream_open);
+        for stream in 0..streams {
+            let quic = client.connection().clone();
+            let overall = client.overall_deadline();
+            opening.spawn(async move {
+                let open = client::open_bi(&quic);
+                (connection, stream, timeouts::within(Phase::StreamOpen, deadline, overall, open).await)
+            });
+        }
+    }
+
+    let mut replies = Vec::new();
+    let mut opened = Vec::new();
+    while let Some(joined) = opening.join_next().await {
+        match joined {
+            Ok((connection, stream, Ok(pair))) => opened.push((connection, stream, pair)),
+            Ok((connection, stream, Err(e))) => {
+                replies.push(StreamReply { connection, stream, stream_id: None, result: Err(e) })
+            }
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    let start = Instant::now();
+    let mut exchanges = JoinSet::new();
+    for (connection, stream, (send, recv)) in opened {
+        let client = &clients[connection];
+        let deadline = timeouts::deadline(client.timeouts().reply);
+        let stream_id = Some(send.id().into_u64());
+        let expected = client.expected_reply().to_vec();
+        let overall = client.overall_deadline();
+        let until = [deadline, overall].into_iter().flatten().min();
+        let ping = timeouts::within(Phase::Reply, deadline, overall, exchange(send, recv, expected, until));
+        exchanges.spawn(async move {
+            let result = ping.await.map(|replied| replied - start);
+            StreamReply { connection, stream, stream_id, result }
+        });
+    }
+    while let Some(joined) = exchanges.join_next().await {
+        match joined {
+            Ok(reply) => replies.push(reply),
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    replies.sort_by_key(|reply| (reply.connection, reply.stream));
+    Round { replies }
+}
+
+/// Writes a `ping` and waits for the `expected` reply, reading while the server
+/// acknowledges, then for the end of the stream until `until` at most. Returns when
+/// the reply arrived.
+async fn exchange(mut send: SendStream,
+                  mut recv: RecvStream,
+                  expected: Vec<u8>,
+                  until: Option<Instant>) -> Result<Instant, ClientError> {
+    send.write_all(client::PING).await.map_err(ClientError::Write)?;
+    let reading = async {
+        let reply = client::read_reply(&mut recv, expected.len()).await?;
+        Ok((reply, Instant::now()))
+    };
+    let (_, (mut reply, replied)) = tokio::try_join!(client::finish(&mut send), reading)?;
+    client::read_reply_end(&mut recv, &mut reply, expected.len(), until).await?;
+    if reply != expected {
+        return Err(ClientError::ProtocolMismatch { expected, received: reply });
+    }
+    Ok(replied)
+}
+
+/// Per-stream and head-of-line statistics over fan-out rounds.
+#[derive(Debug, Default)]
+pub struct FanoutStats {
+    streams: BTreeMap<(usize, usize), Stats>,
+    all: Stats,
+    spreads: Vec<Duration>,
+    ratios: Vec<f64>,
+}
+
+impl FanoutStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records every reply of `round`.
+    pub fn record(&mut self, round: &Round) {
+        for reply in &round.replies {
+            let stats = self.streams.entry((reply.connection, reply.stream)).or_default();
+            stats.record_sent();
+            self.all.record_sent();
+            if let Ok(rtt) = reply.result {
+                stats.record_reply(rtt);
+                self.all.record_reply(rtt);
+            }
+        }
+        if let (Some(fastest), Some(slowest)) = (round.fastest(), round.slowest()) {
+            self.spreads.push(slowest - fastest);
+            if !fastest.is_zero() {
+                self.ratios.push(slowest.as_secs_f64() / fastest.as_secs_f64());
+            }
+        }
+    }
+
+    /// Replies received over all streams.
+    pub fn received(&self) -> u64 {
+        self.all.received()
+    }
+
+    /// Statistics of the stream `stream` of connection `connection`.
+    pub fn stream(&self, connection: usize, stream: usize) -> Option<&Stats> {
+        self.streams.get(&(connection, stream))
+    }
+
+    /// Statistics over all streams.
+    pub fn all(&self) -> &Stats {
+        &self.all
+    }
+
+    /// Median over the rounds of the slowest reply divided by the fastest one.
+    pub fn median_slowdown(&self) -> Option<f64> {
+        let mut ratios = self.ratios.clone();
+        ratios.sort_by(f64::total_cmp);
+        ratios.get(ratios.len() / 2).copied()
+    }
+}
+
+impl fmt::Display for FanoutStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for ((connection, stream), stats) in &self.streams {
+            write!(f, "connection {} stream {}: {} received, {:.1}% loss",
+                   connection + 1, stream + 1, stats.received(), stats.loss_percent())?;
+            if let Some(rtt) = stats.rtt_summary() {
+                write!(f, ", rtt min/avg/max = {}/{}/{} ms", millis(rtt.min), millis(rtt.avg), millis(rtt.max))?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "{}", self.all)?;
+
+        if let (Some(min), Some(max)) = (self.spreads.iter().min(), self.spreads.iter().max()) {
+            let avg = self.spreads.iter().sum::<Duration>() / self.spreads.len() as u32;
+            write!(f, "\nhead-of-line spread min/avg/max = {}/{}/{} ms",
+                   millis(*min), millis(avg), millis(*max))?;
+        }
+        if let Some(slowdown) = self.median_slowdown() {
+            write!(f, "\nslowest stream of a round took {:.2}x the fastest (median)", slowdown)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_of(rtts: &[Option<u64>]) -> Round {
+        let replies = rtts
+            .iter()
+            .enumerate()
+            .map(|(stream, rtt)| StreamReply {
+                connection: 0,
+                stream,
+                stream_id: Some(stream as u64 * 4),
+                result: rtt.map(Duration::from_millis).ok_or(ClientError::TimeOut(Phase::Reply)),
+            })
+            .collect();
+        Round { replies }
+    }
+
+    #[test]
+    fn spread_ignores_lost_streams() {
+        let round = round_of(&[Some(3), None, Some(9), Some(5)]);
+
+        assert_eq!(round.received(), 3);
+        assert_eq!(round.spread(), Some(Duration::from_millis(6)));
+    }
+
+    #[test]
+    fn statistics_per_stream_and_over_rounds() {
+        let mut stats = FanoutStats::new();
+        stats.record(&round_of(&[Some(2), Some(4)]));
+        stats.record(&round_of(&[Some(2), None]));
+        stats.record(&round_of(&[Some(3), Some(9)]));
+
+        assert_eq!(stats.received(), 5);
+        assert_eq!(stats.stream(0, 1).unwrap().received(), 2);
+        assert_eq!(stats.stream(0, 1).unwrap().loss_percent(), 100.0 / 3.0);
+        assert_eq!(stats.median_slowdown(), Some(2.0));
+    }
+}
@@ -0,0 +1,639 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::future;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time;
+use url::Url;
+
+use crate::errors::ClientError;
+use crate::stats::Stats;
+use crate::target;
+use crate::timeouts::Phase;
+use crate::timeouts::Timeouts;
+use crate::tls;
+use crate::tls::ClientIdentity;
+use crate::tls::Trust;
+use crate::ClientOptions;
+use crate::PingClient;
+
+/// Fleet of servers to probe, read from a TOML file:
+///
+/// ```toml
+/// [defaults]
+/// count = 3
+/// ca_file = "fleet-ca.pem"
+///
+/// [[target]]
+/// name = "eu-1"
+/// url = "https://eu-1.example.com:4433/"
+///
+/// [[target]]
+/// url = "https://[2001:db8::1]:4433/"
+/// server_name = "us-1.example.com"
+/// transport = "datagram"
+/// ```
+///
+/// or from the same structure in YAML when the file ends in `.yaml` or `.yml`:
+///
+/// ```yaml
+/// defaults:
+///   count: 3
+///   ca_file: fleet-ca.pem
+/// target:
+///   - name: eu-1
+///     url: https://eu-1.example.com:4433/
+/// ```
+///
+/// Every target takes the settings of [`Settings`]; those it leaves out are taken
+/// from `[defaults]`, then from the defaults of the client.
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    pub targets: Vec<FleetTarget>,
+}
+
+/// Settings of a target, or of `[defaults]`, as written in the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// Name reported for the target; defaults to its URL.
+    pub name: Option<String>,
+    /// WebTransport URL of the server; required for every target.
+    pub url: Option<Url>,
+    /// TLS server name; defaults to the host of the URL.
+    pub server_name: Option<String>,
+    /// Reply expected to a stream ping; defaults to `pong`. Datagram pings are
+    /// echoed, so a target pinging over datagrams cannot set it.
+    pub expect: Option<String>,
+    /// Transport carrying the pings; defaults to `bi`.
+    pub transport: Option<PingTransport>,
+    /// Pings sent; defaults to 1.
+    pub count: Option<u64>,
+    /// Seconds between pings, more than 0; defaults to 1.
+    pub interval: Option<f64>,
+    /// Seconds the whole probe of the target may take; unbounded by default.
+    pub timeout: Option<f64>,
+    pub connect_timeout: Option<f64>,
+    pub session_timeout: Option<f64>,
+    pub stream_open_timeout: Option<f64>,
+    pub reply_timeout: Option<f64>,
+    pub close_timeout: Option<f64>,
+    /// PEM bundle of the CAs to verify the server against.
+    pub ca_file: Option<PathBuf>,
+    /// SHA-256 hashes of accepted server certificates, in hex.
+    pub pin: Option<Vec<String>>,
+    /// Accept any server certificate.
+    pub insecure: Option<bool>,
+    /// Client certificate chain and key, for servers that authenticate clients.
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub key_password_file: Option<PathBuf>,
+}
+
+/// Transport carrying the pings of a fleet target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PingTransport {
+    /// A new bidirectional stream per ping.
+    #[default]
+    Bi,
+    /// A new unidirectional stream per ping, answered on one the server opens.
+    Uni,
+    /// Sequence-numbered datagrams echoed by the server.
+    Datagram,
+}
+
+impl PingTransport {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PingTransport::Bi => "bi",
+            PingTransport::Uni => "uni",
+            PingTransport::Datagram => "datagram",
+        }
+    }
+}
+
+/// A target of the fleet with its settings resolved.
+#[derive(Debug, Clone)]
+pub struct FleetTarget {
+    pub name: String,
+    pub url: Url,
+    pub options: ClientOptions,
+    pub transport: PingTransport,
+    pub count: u64,
+    pub interval: Duration,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    defaults: Settings,
+    #[serde(default, rename = "target")]
+    targets: Vec<Settings>,
+}
+
+impl Fleet {
+    /// Reads and resolves the fleet described at `path`, in YAML when its extension
+    /// is `.yaml` or `.yml`, in TOML otherwise.
+    ///
+    /// Relative file paths in the settings are relative to the directory of `path`.
+    pub fn load(path: &Path) -> Result<Self, ClientError> {
+        let error = |source| ClientError::Config { path: path.to_path_buf(), source };
+        let text = fs::read_to_string(path).map_err(error)?;
+        let file: File = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| error(invalid_data(e)))?,
+            _ => match toml::from_str(&text) {
+                Ok(file) => file,
+                Err(e) => {
+                    let line = e.span().map_or(1, |span| text[..span.start].matches('\n').count() + 1);
+                    return Err(error(invalid_data(format!("line {}: {}", line, e.message()))));
+                }
+            },
+        };
+        if file.defaults.url.is_some() || file.defaults.name.is_some() {
+            return Err(error(invalid_data("[defaults] cannot set a url or a name")));
+        }
+        if file.targets.is_empty() {
+            return Err(error(invalid_data("no [[target]] listed")));
+        }
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut targets = Vec::new();
+        for (i, settings) in file.targets.iter().enumerate() {
+            match settings.resolve(&file.defaults, base) {
+                Ok(target) => targets.push(target),
+                Err(e) => return Err(error(invalid_data(InvalidTarget { number: i + 1, error: e }))),
+            }
+        }
+        Ok(Fleet { targets })
+    }
+
+    /// Probes every target at once.
+    pub async fn probe(&self) -> FleetReport {
+        self.probe_until(future::pending()).await
+    }
+
+    /// Probes every target at once like [`Fleet::probe`], but no target sends another
+    /// ping once `stop` completes; the report covers the pings sent until then.
+    pub async fn probe_until(&self, stop: impl Future<Output = ()>) -> FleetReport {
+        let (stopping, stopped) = watch::channel(false);
+        let mut probes = JoinSet::new();
+        for (i, target) in self.targets.iter().enumerate() {
+            let target = target.clone();
+            let mut stopped = stopped.clone();
+            let stop = async move {
+                if stopped.wait_for(|stopped| *stopped).await.is_err() {
+                    future::pending::<()>().await;
+                }
+            };
+            probes.spawn(async move { (i, target.probe_until(stop).await) });
+        }
+
+        let mut reports = Vec::new();
+        tokio::pin!(stop);
+        loop {
+            let joined = tokio::select! {
+                _ = &mut stop, if !*stopping.borrow() => {
+                    stopping.send_replace(true);
+                    continue;
+                }
+                joined = probes.join_next() => joined,
+            };
+            match joined {
+                Some(Ok(report)) => reports.push(report),
+                Some(Err(e)) => std::panic::resume_unwind(e.into_panic()),
+                None => break,
+            }
+        }
+        reports.sort_by_key(|(i, _)| *i);
+        FleetReport { targets: reports.into_iter().map(|(_, report)| report).collect() }
+    }
+}
+
+impl Settings {
+    /// Settings of this target, falling back to `defaults`, as options of a probe.
+    fn resolve(&self, defaults: &Settings, base: &Path) -> Result<FleetTarget, SettingError> {
+        let url = match &self.url {
+            Some(url) if url.scheme() == "https" && url.host().is_some() => url.clone(),
+            Some(url) => return Err(format!("'{}' is not an https URL", url).into()),
+            None => return Err("url is missing".into()),
+        };
+        let pick = |own: &Option<f64>, default: &Option<f64>| -> Result<Option<Duration>, String> {
+            own.or(*default).map(|secs| Duration::try_from_secs_f64(secs).map_err(|e| e.to_string())).transpose()
+        };
+        let timeout = |own: &Option<f64>, default: &Option<f64>, fallback: Option<Duration>| {
+            match pick(own, default)? {
+                Some(Duration::ZERO) => Ok::<_, String>(None),
+                Some(limit) => Ok(Some(limit)),
+                None => Ok(fallback),
+            }
+        };
+
+        let fallback = Timeouts::default();
+        let timeouts = Timeouts {
+            connect: timeout(&self.connect_timeout, &defaults.connect_timeout, fallback.connect)?,
+            session: timeout(&self.session_timeout, &defaults.session_timeout, fallback.session)?,
+            stream_open: timeout(&self.stream_open_timeout, &defaults.stream_open_timeout, fallback.stream_open)?,
+            reply: timeout(&self.reply_timeout, &defaults.reply_timeout, fallback.reply)?,
+            close: timeout(&self.close_timeout, &defaults.close_timeout, fallback.close)?,
+            overall: timeout(&self.timeout, &defaults.timeout, fallback.overall)?,
+        };
+
+        // Trust and client identity are taken from the target or from the defaults as a
+        // whole, so that a target can pin a hash where the defaults name a CA file.
+        let trusting = match (&self.ca_file, &self.pin, self.insecure) {
+            (None, None, None) => defaults,
+            _ => self,
+        };
+        let trust = trusting.trust(base)?;
+        let identifying = match (&self.cert, &self.key) {
+            (None, None) => defaults,
+            _ => self,
+        };
+        let identity = identifying.identity(base)?;
+
+        let count = self.count.or(defaults.count).unwrap_or(1);
+        if count == 0 {
+            return Err("count must be at least 1".into());
+        }
+        let interval = match pick(&self.interval, &defaults.interval)? {
+            Some(Duration::ZERO) => return Err("interval must be more than 0".into()),
+            Some(interval) => interval,
+            None => Duration::from_secs(1),
+        };
+        let transport = self.transport.or(defaults.transport).unwrap_or_default();
+        let expected_reply = match self.expect.as_ref().or(defaults.expect.as_ref()) {
+            Some(expect) if expect.is_empty() => return Err("expect must not be empty".into()),
+            expect => expect.map(|s| s.as_bytes().to_vec()),
+        };
+        if transport == PingTransport::Datagram && expected_reply.is_some() {
+            return Err("expect does not apply to datagram pings, which are echoed".into());
+        }
+
+        let server_name = self.server_name.as_deref().or(defaults.server_name.as_deref());
+        let server_name = server_name.map(target::parse_server_name).transpose()?;
+
+        Ok(FleetTarget {
+            name: self.name.clone().unwrap_or_else(|| url.to_string()),
+            options: ClientOptions {
+                server_name,
+                timeouts,
+                trust,
+                identity,
+                expected_reply,
+                ..ClientOptions::default()
+            },
+            url,
+            transport,
+            count,
+            interval,
+        })
+    }
+
+    fn trust(&self, base: &Path) -> Result<Trust, String> {
+        match (self.insecure.unwrap_or(false), &self.ca_file, &self.pin) {
+            (true, _, _) => Ok(Trust::Insecure),
+            (false, Some(_), Some(_)) => Err("ca_file and pin are exclusive".to_string()),
+            (false, Some(path), None) => Ok(Trust::CaFile(base.join(path))),
+            (false, None, Some(pins)) => {
+                Ok(Trust::Pinned(pins.iter().map(|pin| tls::parse_digest(pin)).collect::<Result<_, _>>()?))
+            }
+            (false, None, None) => Ok(Trust::System),
+        }
+    }
+
+    fn identity(&self, base: &Path) -> Result<Option<ClientIdentity>, SettingError> {
+        let (cert, key) = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => (base.join(cert), base.join(key)),
+            (None, None) => return Ok(None),
+            _ => return Err("cert and key go together".into()),
+        };
+        let password = match &self.key_password_file {
+            Some(path) => {
+                let path = base.join(path);
+                match tls::read_password(&path) {
+                    Ok(password) => Some(password),
+                    Err(source) => return Err(SettingError::Password { path, source }),
+                }
+            }
+            None => None,
+        };
+        match ClientIdentity::load(&cert, &key, password.as_deref()) {
+            Ok(identity) => Ok(Some(identity)),
+            Err(e) => Err(SettingError::Identity(e)),
+        }
+    }
+}
+
+/// Why the settings of a target cannot be resolved.
+#[derive(Debug)]
+enum SettingError {
+    Invalid(String),
+    /// The file of `key_password_file` could not be read.
+    Password { path: PathBuf, source: io::Error },
+    /// The client certificate or key could not be loaded.
+    Identity(ClientError),
+}
+
+impl From<String> for SettingError {
+    fn from(reason: String) -> Self {
+        SettingError::Invalid(reason)
+    }
+}
+
+impl From<&str> for SettingError {
+    fn from(reason: &str) -> Self {
+        SettingError::Invalid(reason.to_string())
+    }
+}
+
+impl fmt::Display for SettingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingError::Invalid(reason) => f.write_str(reason),
+            SettingError::Password { path, source } => {
+                write!(f, "cannot read the key password from {}: {}", path.display(), source)
+            }
+            SettingError::Identity(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for SettingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SettingError::Invalid(_) => None,
+            SettingError::Password { source, .. } => Some(source),
+            SettingError::Identity(e) => Some(e),
+        }
+    }
+}
+
+/// Target of the fleet file, numbered from 1, whose settings cannot be resolved.
+#[derive(Debug)]
+struct InvalidTarget {
+    number: usize,
+    error: SettingError,
+}
+
+impl fmt::Display for InvalidTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "target {}: {}", self.number, self.error)
+    }
+}
+
+impl Error for InvalidTarget {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+impl FleetTarget {
+    /// Connects and sends `count` pings, `interval` apart.
+    pub async fn probe(self) -> TargetReport {
+        self.probe_until(future::pending()).await
+    }
+
+    /// Probes like [`FleetTarget::probe`], but sends no more pings once `stop`
+    /// completes, letting the ping in flight finish before closing the connection.
+    pub async fn probe_until(self, stop: impl Future<Output = ()>) -> TargetReport {
+        let mut stats = Stats::new();
+        let client = match PingClient::connect(&self.url, &self.options).await {
+            Ok(client) => client,
+            Err(e) => return TargetReport { target: self, stats, error: Some(e) },
+        };
+
+        let mut last_error = None;
+        let mut ticker = time::interval(self.interval);
+        tokio::pin!(stop);
+        for _ in 0..self.count {
+            tokio::select! {
+                _ = &mut stop => break,
+                _ = ticker.tick() => {}
+            }
+            stats.record_sent();
+            let result = match self.transport {
+                PingTransport::Bi => client.ping().await.map(|pong| pong.rtt),
+                PingTransport::Uni => client.ping_uni().await.map(|pong| pong.rtt),
+                PingTransport::Datagram => client.ping_datagram().await,
+            };
+            match result {
+                Ok(rtt) => stats.record_reply(rtt),
+                Err(e) => {
+                    let expired = matches!(e, ClientError::TimeOut(Phase::Overall));
+                    last_error = Some(e);
+                    if expired {
+                        break;
+                    }
+                }
+            }
+        }
+        client.close().await;
+        TargetReport { target: self, stats, error: last_error }
+    }
+}
+
+/// Health of a target, or of the whole fleet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Health {
+    /// Every ping was answered.
+    Healthy,
+    /// Some pings were answered.
+    Degraded,
+    /// No ping was answered.
+    Down,
+}
+
+impl Health {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Health::Healthy => "healthy",
+            Health::Degraded => "degraded",
+            Health::Down => "down",
+        }
+    }
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Result of probing a target.
+#[derive(Debug)]
+pub struct TargetReport {
+    pub target: FleetTarget,
+    pub stats: Stats,
+    /// Last error of the probe.
+    pub error: Option<ClientError>,
+}
+
+impl TargetReport {
+    pub fn health(&self) -> Health {
+        match (self.stats.received(), self.stats.transmitted()) {
+            (0, _) => Health::Down,
+            (received, transmitted) if received < transmitted => Health::Degraded,
+            _ => Health::Healthy,
+        }
+    }
+}
+
+/// Results of probing a fleet, in the order of its targets.
+#[derive(Debug)]
+pub struct FleetReport {
+    pub targets: Vec<TargetReport>,
+}
+
+impl FleetReport {
+    /// Number of targets in `health`.
+    pub fn count(&self, health: Health) -> usize {
+        self.targets.iter().filter(|report| report.health() == health).count()
+    }
+
+    /// Healthy when every target is, down when every target is, degraded otherwise.
+    pub fn verdict(&self) -> Health {
+        let healthy = self.count(Health::Healthy);
+        let down = self.count(Health::Down);
+        if healthy == self.targets.len() {
+            Health::Healthy
+        } else if down == self.targets.len() {
+            Health::Down
+        } else {
+            Health::Degraded
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Vec<FleetTarget>, String> {
+        let file: File = toml::from_str(text).map_err(|e| e.to_string())?;
+        resolve(file).map_err(|e| e.to_string())
+    }
+
+    fn resolve(file: File) -> Result<Vec<FleetTarget>, SettingError> {
+        file.targets.iter().map(|target| target.resolve(&file.defaults, Path::new("/etc/fleet"))).collect()
+    }
+
+    #[test]
+    fn targets_fall_back_to_defaults() {
+        let targets = parse(r#"
+            [defaults]
+            count = 3
+            reply_timeout = 0.5
+            ca_file = "ca.pem"
+
+            [[target]]
+            name = "eu-1"
+            url = "https://eu-1.example.com:4433/"
+            expect = "quack-ack"
+
+            [[target]]
+            url = "https://us-1.example.com:4433/"
+            transport = "datagram"
+            count = 1
+            connect_timeout = 0
+            insecure = true
+        "#).unwrap();
+
+        assert_eq!(targets[0].name, "eu-1");
+        assert_eq!(targets[0].count, 3);
+        assert_eq!(targets[0].transport, PingTransport::Bi);
+        assert_eq!(targets[0].options.timeouts.reply, Some(Duration::from_millis(500)));
+        assert!(matches!(&targets[0].options.trust, Trust::CaFile(path) if path == Path::new("/etc/fleet/ca.pem")));
+        assert_eq!(targets[0].options.expected_reply.as_deref(), Some(&b"quack-ack"[..]));
+
+        assert_eq!(targets[1].name, "https://us-1.example.com:4433/");
+        assert_eq!(targets[1].count, 1);
+        assert_eq!(targets[1].transport, PingTransport::Datagram);
+        assert_eq!(targets[1].options.timeouts.connect, None);
+        assert!(matches!(targets[1].options.trust, Trust::Insecure));
+        assert_eq!(targets[1].options.expected_reply, None);
+    }
+
+    #[test]
+    fn reads_yaml() {
+        let file: File = serde_yaml::from_str("
+            defaults:
+              count: 3
+            target:
+              - name: eu-1
+                url: https://eu-1.example.com:4433/
+              - url: https://us-1.example.com:4433/
+                transport: uni
+                interval: 0.5
+        ").unwrap();
+        let targets = resolve(file).unwrap();
+
+        assert_eq!(targets[0].name, "eu-1");
+        assert_eq!(targets[0].count, 3);
+        assert_eq!(targets[1].transport, PingTransport::Uni);
+        assert_eq!(targets[1].interval, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn rejects_invalid_targets() {
+        assert!(parse("[[target]]\nname = \"x\"").unwrap_err().contains("url is missing"));
+        assert!(parse("[[target]]\nurl = \"http://a/\"").unwrap_err().contains("not an https URL"));
+        assert!(parse("[[target]]\nurl = \"https://a/\"\npin = [\"zz\"]").unwrap_err().contains("SHA-256"));
+        assert!(parse("[[target]]\nurl = \"https://a/\"\nport = 1").unwrap_err().contains("unknown field"));
+        assert!(parse("[[target]]\nurl = \"https://a/\"\ninterval = 0").unwrap_err().contains("more than 0"));
+        assert!(parse("[defaults]\nexpect = \"x\"\n[[target]]\nurl = \"https://a/\"\ntransport = \"datagram\"")
+            .unwrap_err()
+            .contains("datagram"));
+        assert!(parse("[[target]]\nurl = \"https://a/\"\nexpect = \"\"").unwrap_err().contains("must not be empty"));
+        assert!(parse("[defaults]\nserver_name = \"10.0.0.1\"\n[[target]]\nurl = \"https://a/\"")
+            .unwrap_err()
+            .contains("DNS name"));
+        assert!(parse("[[target]]\nurl = \"https://a/\"\nserver_name = \"a b\"").is_err());
+    }
+
+    #[test]
+    fn keeps_the_cause_of_an_unloadable_identity() {
+        let file: File = toml::from_str("[[target]]\nurl = \"https://a/\"\ncert = \"missing.pem\"\nkey = \"missing.key\"")
+            .unwrap();
+        match resolve(file) {
+            Err(SettingError::Identity(ClientError::IdentityFile { path, source })) => {
+                assert_eq!(path, Path::new("/etc/fleet/missing.pem"));
+                assert_eq!(source.kind(), io::ErrorKind::NotFound);
+            }
+            other => panic!("unexpected {:?}", other.map(|targets| targets.len())),
+        }
+    }
+
+    #[test]
+    fn verdict_of_the_fleet() {
+        let target = parse("[[target]]\nurl = \"https://a/\"").unwrap().remove(0);
+        let report = |sent: u64, received: u64| {
+            let mut stats = Stats::new();
+            for i in 0..sent {
+                stats.record_sent();
+                if i < received {
+                    stats.record_reply(Duration::from_millis(1));
+                }
+            }
+            TargetReport { target: target.clone(), stats, error: None }
+        };
+
+        assert_eq!(FleetReport { targets: vec![report(2, 2), report(1, 1)] }.verdict(), Health::Healthy);
+        assert_eq!(FleetReport { targets: vec![report(2, 2), report(2, 1)] }.verdict(), Health::Degraded);
+        assert_eq!(FleetReport { targets: vec![report(2, 2), report(1, 0)] }.verdict(), Health::Degraded);
+        assert_eq!(FleetReport { targets: vec![report(2, 0), report(1, 0)] }.verdict(), Health::Down);
+    }
+}
@@ -0,0 +1,41 @@
+//! WebTransport ping-pong client.
+//!
+//! [`PingClient`] connects to a ping-pong server and measures round trips over
+//! bidirectional streams or datagrams:
+//!
+//! ```no_run
+//! # use ping_pong_client::{ClientError, ClientOptions, PingClient};
+//! # async fn probe() -> Result<(), ClientError> {
+//! let url = "https://localhost:4433/".parse().unwrap();
+//! let client = PingClient::connect(&url, &ClientOptions::default()).await?;
+//! let pong = client.ping().await?;
+//! client.close().await;
+//! # Ok(())
+//! # }
+//! ```
+pub mod datagram;
+pub mod errors;
+pub mod fanout;
+pub mod fleet;
+pub mod metrics;
+pub mod reconnect;
+pub mod reuse;
+pub mod stats;
+pub mod target;
+pub mod throughput;
+pub mod timeouts;
+pub mod tls;
+
+mod client;
+mod qlog;
+
+pub use client::ClientOptions;
+pub use client::Closed;
+pub use client::CLOSE_NORMAL;
+pub use client::ConnectTimings;
+pub use client::PingClient;
+pub use client::Pong;
+pub use client::PING;
+pub use client::PONG;
+pub use errors::ClientError;
+pub use errors::ErrorKind;
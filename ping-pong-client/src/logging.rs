@@ -0,0 +1,33 @@
+use std::io;
+use std::io::IsTerminal;
+
+use tracing_subscriber::EnvFilter;
+
+use crate::cli::LogFormat;
+
+/// Logs to standard error at the level of `verbosity`, unless `RUST_LOG` sets the filter.
+pub fn init(verbosity: u8, format: LogFormat) {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::new(directives(verbosity)),
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().with_span_list(true).init(),
+    }
+}
+
+/// Filter of `-v` repeated `verbosity` times: warnings only by default, then the
+/// client's pings and connections, their steps, and finally every crate's trace.
+fn directives(verbosity: u8) -> &'static str {
+    match verbosity {
+        0 => "warn",
+        1 => "warn,ping_pong_client=info",
+        2 => "warn,ping_pong_client=debug",
+        _ => "trace",
+    }
+}
@@ -1,55 +1,503 @@
-use std::net::Ipv6Addr;
-use std::net::SocketAddr;
+use std::future;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use std::time::Instant;
 
-use wtransport::ClientConfig;
-use wtransport::Endpoint;
+use clap::error::ErrorKind;
+use clap::CommandFactory;
+use clap::Parser;
+use tokio::time;
+use tracing::warn;
 
-use errors::ClientError;
+use cli::Cli;
+use cli::Transport;
+use output::Outcome;
+use output::Output;
+use output::Ping;
+use shutdown::Shutdown;
+use ping_pong_client::datagram::DatagramTracker;
+use ping_pong_client::datagram::Echo;
+use ping_pong_client::fanout;
+use ping_pong_client::fanout::FanoutStats;
+use ping_pong_client::fleet::Fleet;
+use ping_pong_client::fleet::Health;
+use ping_pong_client::reconnect;
+use ping_pong_client::reuse;
+use ping_pong_client::reuse::ReuseStats;
+use ping_pong_client::stats::Stats;
+use ping_pong_client::throughput::BulkOptions;
+use ping_pong_client::throughput::BulkTransport;
+use ping_pong_client::timeouts::Phase;
+use ping_pong_client::tls;
+use ping_pong_client::tls::ClientIdentity;
+use ping_pong_client::tls::KeyLog;
+use ping_pong_client::tls::SessionCache;
+use ping_pong_client::ClientError;
+use ping_pong_client::ClientOptions;
+use ping_pong_client::PingClient;
 
-pub mod errors;
-
-const PORT: u16 = 4433;
-const HOST: Ipv6Addr = Ipv6Addr::LOCALHOST;
-const LOCALHOST: &str = "localhost";
+mod cli;
+mod daemon;
+mod logging;
+mod output;
+mod shutdown;
 
+/// Wait for the echoes of the last datagram pings when the reply timeout is disabled.
+const DATAGRAM_LINGER: Duration = Duration::from_secs(5);
 
 #[tokio::main]
-async fn main() -> Result<(), ClientError> {
-    let addr = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0);
-    let config =
-        ClientConfig::builder().with_bind_address(addr);
-
-    println!("Bind address: {:?}.", addr);
-
-    let conn = match Endpoint::client(config) {
-        Ok(endpoint) => match endpoint.connect(SocketAddr::new(HOST.into(), PORT),
-                                               LOCALHOST) {
-            Ok(conn) => {
-                match conn.await {
-                    Ok(connection) => connection,
-                    Err(_) => return Err(ClientError::TimeOut),
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    logging::init(cli.verbose, cli.log_format);
+    if cli.urls.len() > 1 && cli.metrics.is_none() {
+        Cli::command().error(ErrorKind::TooManyValues, "several URLs are only probed with --metrics").exit();
+    }
+    let source = match &cli.config {
+        Some(path) => path.display().to_string(),
+        None => cli.url().to_string(),
+    };
+    let mut output = Output::new(cli.format, &source, transport_name(&cli));
+    let mut shutdown = Shutdown::listen();
+    if let Some(path) = &cli.config {
+        return match Fleet::load(path).and_then(|fleet| with_traces(fleet, &cli)) {
+            Ok(fleet) => {
+                let report = fleet.probe_until(shutdown.requested()).await;
+                output.fleet(&report);
+                match report.verdict() {
+                    Health::Healthy => ExitCode::SUCCESS,
+                    Health::Degraded | Health::Down => ExitCode::FAILURE,
                 }
             }
-            Err(_) => return Err(ClientError::LocallyClosed),
-        },
-        Err(_) => return Err(ClientError::QuicError),
+            Err(e) => {
+                output.error(&e);
+                ExitCode::from(e.kind().exit_code())
+            }
+        };
+    }
+    match run(&cli, &mut output, shutdown).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            output.error(&e);
+            ExitCode::from(e.kind().exit_code())
+        }
+    }
+}
+
+async fn run(cli: &Cli, output: &mut Output, shutdown: Shutdown) -> Result<(), ClientError> {
+    let identity = match (&cli.cert, &cli.key) {
+        (Some(cert), Some(key)) => Some(load_identity(cert, key, cli.key_password_file.as_deref())?),
+        _ => None,
+    };
+    let options = ClientOptions {
+        bind: cli.bind,
+        server_name: cli.server_name.clone(),
+        ip_version: cli.ip_version(),
+        timeouts: cli.timeouts(),
+        trust: cli.trust(),
+        identity,
+        expected_reply: None,
+        sessions: cli.resume.then(SessionCache::new),
+        key_log: key_log(cli)?,
+        qlog_dir: cli.qlog_dir.clone(),
     };
 
-    println!("Connected: port – {:?}, host – {:?}.", PORT, HOST);
+    if cli.insecure {
+        warn!("server certificate verification is disabled (--insecure); \
+               the connection is open to interception. Use it for local development only.");
+    }
+
+    if let Some(address) = cli.metrics {
+        let server = match tiny_http::Server::http(address) {
+            Ok(server) => server,
+            Err(e) => return Err(ClientError::MetricsListen { address, source: io::Error::other(e) }),
+        };
+        println!("Serving metrics on http://{}/metrics.", address);
+        daemon::run(server, &cli.urls, &options, cli.interval, cli.backoff(), shutdown).await;
+        return Ok(());
+    }
+
+    let mut client = PingClient::connect(cli.url(), &options).await?;
+    output.connected(&client);
 
-    let mut stream = match conn.open_bi().await {
-        Ok(s) => s,
-        Err(_) => return Err(ClientError::StreamOpeningError)
+    if let Some(options) = cli.bulk_options() {
+        let result = throughput(&client, &options, output, shutdown).await;
+        output.closed(&client.close().await);
+        return result;
+    }
+
+    if let Some(streams) = cli.fanout {
+        let mut clients = vec![client];
+        for _ in 1..cli.connections {
+            clients.push(PingClient::connect(cli.url(), &options).await?);
+        }
+        let (stats, last_error) = fan_out(&clients, streams, cli, output, shutdown).await;
+        output.fanout_summary(&stats, last_error.as_ref().filter(|_| stats.received() == 0));
+        for client in clients {
+            output.closed(&client.close().await);
+        }
+        return match last_error {
+            Some(e) if stats.received() == 0 => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    if cli.compare_reuse {
+        let (stats, last_error) = compare_reuse(&client, &options, cli, output, shutdown).await;
+        let answered = stats.fresh().received() + stats.resumed().received() + stats.reused().received();
+        output.reuse_summary(&stats, last_error.as_ref().filter(|_| answered == 0));
+        output.closed(&client.close().await);
+        return match last_error {
+            Some(e) if answered == 0 => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    let (stats, last_error) = match cli.transport {
+        Transport::Bi | Transport::Uni => ping_streams(&mut client, &options, cli, output, shutdown).await,
+        Transport::Datagram => ping_datagrams(&mut client, &options, cli, output, shutdown).await,
     };
 
-    let _res = match stream.0.write_all(b"ping").await {
-        Ok(_) => {
-            match stream.0.finish().await {
-                Ok(_) => Ok(()),
-                Err(_) => return Err(ClientError::TimeOut),
+    output.summary(&stats, last_error.as_ref().filter(|_| stats.received() == 0));
+    output.closed(&client.close().await);
+
+    match last_error {
+        Some(e) if stats.received() == 0 => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Runs a throughput test, reporting each interval as it ends and then the summary,
+/// ending it early with the data in flight on shutdown.
+async fn throughput(client: &PingClient,
+                    options: &BulkOptions,
+                    output: &Output,
+                    mut shutdown: Shutdown) -> Result<(), ClientError> {
+    let on_interval = |interval: &_| output.interval(interval);
+    let report = client.throughput_until(options, on_interval, shutdown.requested()).await?;
+    output.throughput_summary(&report);
+    Ok(())
+}
+
+/// Pings over a new bidirectional or, with `--transport uni`, unidirectional stream per
+/// ping until `count` pings were sent or shutdown, which lets the ping in flight finish.
+async fn ping_streams(client: &mut PingClient,
+                      options: &ClientOptions,
+                      cli: &Cli,
+                      output: &mut Output,
+                      mut shutdown: Shutdown) -> (Stats, Option<ClientError>) {
+    let mut stats = Stats::new();
+    let mut last_error = None;
+    let mut seq: u64 = 0;
+    let mut interval = time::interval(cli.interval);
+    let mut replied_at = Instant::now();
+
+    loop {
+        tokio::select! {
+            _ = shutdown.requested() => break,
+            _ = overall_expired(client) => {
+                last_error = Some(ClientError::TimeOut(Phase::Overall));
+                break;
             }
+            _ = interval.tick() => {}
         }
-        Err(_) => Err(ClientError::TimeOut),
-    };
+        seq += 1;
+
+        stats.record_sent();
+        let result = match cli.transport {
+            Transport::Uni => client.ping_uni().await,
+            _ => client.ping().await,
+        };
+        match result {
+            Ok(pong) => {
+                replied_at = Instant::now();
+                stats.record_reply(pong.rtt);
+                output.ping(&Ping { seq, connection: None, stream_id: Some(pong.stream_id), outcome: Outcome::Pong(pong.rtt) });
+            }
+            Err(e) => {
+                output.ping(&Ping { seq, connection: None, stream_id: None, outcome: Outcome::Failed(&e) });
+                let expired = matches!(e, ClientError::TimeOut(Phase::Overall));
+                last_error = Some(e);
+                if expired {
+                    break;
+                }
+            }
+        }
+
+        if let (true, Some(cause)) = (cli.reconnect, client.lost()) {
+            tokio::select! {
+                _ = shutdown.requested() => break,
+                restored = restore(client, replied_at, cause, options, cli, output) => if let Err(e) = restored {
+                    last_error = Some(e);
+                    break;
+                },
+            }
+            interval.reset();
+        }
+
+        if seq == cli.count {
+            break;
+        }
+    }
+    (stats, last_error)
+}
+
+/// Pings over `streams` streams of every client at once, a round per interval, until
+/// `count` rounds ran or shutdown, which lets the round in flight finish.
+async fn fan_out(clients: &[PingClient],
+                 streams: usize,
+                 cli: &Cli,
+                 output: &Output,
+                 mut shutdown: Shutdown) -> (FanoutStats, Option<ClientError>) {
+    let mut stats = FanoutStats::new();
+    let mut last_error = None;
+    let mut seq: u64 = 0;
+    let mut interval = time::interval(cli.interval);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.requested() => break,
+            _ = overall_expired(&clients[0]) => {
+                last_error = Some(ClientError::TimeOut(Phase::Overall));
+                break;
+            }
+            _ = interval.tick() => {}
+        }
+        seq += 1;
+
+        let round = fanout::round(clients, streams).await;
+        output.round(seq, &round);
+        stats.record(&round);
+
+        let mut expired = false;
+        for reply in round.replies {
+            if let Err(e) = reply.result {
+                expired |= matches!(e, ClientError::TimeOut(Phase::Overall));
+                last_error = Some(e);
+            }
+        }
+        if expired || seq == cli.count {
+            break;
+        }
+    }
+    (stats, last_error)
+}
+
+/// Pings over a fresh connection with a full handshake, over another resuming a TLS
+/// session if `options` keep sessions, then over `client`, a round per interval,
+/// until `count` rounds ran or shutdown, which lets the round in flight finish.
+async fn compare_reuse(client: &PingClient,
+                       options: &ClientOptions,
+                       cli: &Cli,
+                       output: &Output,
+                       mut shutdown: Shutdown) -> (ReuseStats, Option<ClientError>) {
+    let mut stats = ReuseStats::new();
+    let mut last_error = None;
+    let mut seq: u64 = 0;
+    let mut interval = time::interval(cli.interval);
+    let full = ClientOptions { sessions: None, ..options.clone() };
+
+    loop {
+        tokio::select! {
+            _ = shutdown.requested() => break,
+            _ = overall_expired(client) => {
+                last_error = Some(ClientError::TimeOut(Phase::Overall));
+                break;
+            }
+            _ = interval.tick() => {}
+        }
+        seq += 1;
+
+        let fresh = reuse::fresh_ping(cli.url(), &full, client.overall_deadline()).await;
+        let resumed = match options.sessions {
+            Some(_) => Some(reuse::fresh_ping(cli.url(), options, client.overall_deadline()).await),
+            None => None,
+        };
+        let reused = client.ping().await;
+        output.comparison(seq, &fresh, resumed.as_ref(), &reused);
+        stats.record_fresh(fresh.as_ref().ok());
+        if let Some(resumed) = &resumed {
+            stats.record_resumed(resumed.as_ref().ok());
+        }
+        stats.record_reused(reused.as_ref().ok().map(|pong| pong.rtt));
+
+        let mut expired = false;
+        let resumed = resumed.and_then(Result::err);
+        for e in [fresh.err(), resumed, reused.err()].into_iter().flatten() {
+            expired |= matches!(e, ClientError::TimeOut(Phase::Overall));
+            last_error = Some(e);
+        }
+        if expired || seq == cli.count {
+            break;
+        }
+    }
+    (stats, last_error)
+}
+
+/// Pings with sequence-numbered datagrams, matching the server's echoes to the pings
+/// they answer, until `count` pings were sent and answered or shutdown, after which
+/// the echoes in flight are still awaited up to the reply timeout, or
+/// [`DATAGRAM_LINGER`] without one.
+async fn ping_datagrams(client: &mut PingClient,
+                        options: &ClientOptions,
+                        cli: &Cli,
+                        output: &mut Output,
+                        mut shutdown: Shutdown) -> (Stats, Option<ClientError>) {
+    let mut tracker = DatagramTracker::new();
+    let mut last_error = None;
+    let mut lost = None;
+    let mut replied_at = Instant::now();
+    let mut seq: u64 = 0;
+    let mut interval = time::interval(cli.interval);
+    let linger_for = options.timeouts.reply.unwrap_or(DATAGRAM_LINGER);
+    let linger = time::sleep(Duration::MAX);
+    tokio::pin!(linger);
+
+    loop {
+        if let Some(cause) = lost.take() {
+            tokio::select! {
+                _ = shutdown.requested() => break,
+                restored = restore(client, replied_at, cause, options, cli, output) => if let Err(e) = restored {
+                    last_error = Some(e);
+                    break;
+                },
+            }
+            interval.reset();
+        }
+
+        let sending = (cli.count == 0 || seq < cli.count) && !shutdown.is_requested();
+        tokio::select! {
+            _ = shutdown.requested(), if sending => match tracker.is_complete() {
+                true => break,
+                false => linger.as_mut().reset(time::Instant::now() + linger_for),
+            },
+            _ = overall_expired(client) => {
+                last_error = Some(ClientError::TimeOut(Phase::Overall));
+                break;
+            }
+            _ = interval.tick(), if sending => {
+                seq += 1;
+                match client.send_datagram(seq) {
+                    Ok(()) => tracker.on_sent(seq, Instant::now()),
+                    Err(e) => match (cli.reconnect, client.lost()) {
+                        (true, Some(cause)) => lost = Some(cause),
+                        _ => {
+                            last_error = Some(e);
+                            break;
+                        }
+                    },
+                }
+                if seq == cli.count {
+                    linger.as_mut().reset(time::Instant::now() + linger_for);
+                }
+            }
+            received = client.receive_datagram() => {
+                let payload = match received {
+                    Ok(payload) => payload,
+                    Err(e) if cli.reconnect => {
+                        lost = Some(e);
+                        continue;
+                    }
+                    Err(e) => {
+                        last_error = Some(e);
+                        break;
+                    }
+                };
+                replied_at = Instant::now();
+                let (seq, outcome) = match tracker.on_echo(&payload, replied_at) {
+                    Echo::Reply { seq, rtt } => (seq, Outcome::Pong(rtt)),
+                    Echo::Reordered { seq, rtt } => (seq, Outcome::Reordered(rtt)),
+                    Echo::Duplicate { seq } => (seq, Outcome::Duplicate),
+                    Echo::Unknown => continue,
+                };
+                output.ping(&Ping { seq, connection: None, stream_id: None, outcome });
+                if !sending && tracker.is_complete() {
+                    break;
+                }
+            }
+            _ = &mut linger => break,
+        }
+    }
+
+    for seq in tracker.unanswered() {
+        output.ping(&Ping { seq, connection: None, stream_id: None, outcome: Outcome::Lost });
+    }
+    if last_error.is_none() && tracker.stats().received() == 0 {
+        last_error = Some(ClientError::TimeOut(Phase::Reply));
+    }
+    (tracker.into_stats(), last_error)
+}
+
+/// Replaces `client`, whose connection was lost with `cause` after its last reply
+/// `since`, by a new connection, retrying with the backoff of the command line and
+/// reporting the outage. Fails with the last error once reconnecting was given up.
+async fn restore(client: &mut PingClient,
+                 since: Instant,
+                 cause: ClientError,
+                 options: &ClientOptions,
+                 cli: &Cli,
+                 output: &mut Output) -> Result<(), ClientError> {
+    output.reconnecting(&cause);
+    let since = time::Instant::from_std(since);
+    let (result, outage) =
+        reconnect::reconnect(cli.url(), options, &cli.backoff(), client.overall_deadline(), since, cause).await;
+    output.outage(&outage, result.as_ref().err());
+    let restored = result?;
+    output.connected(&restored);
+    mem::replace(client, restored).close().await;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Transport named in the results: `bi`, `uni` or `datagram`.
+fn transport_name(cli: &Cli) -> &'static str {
+    match (cli.bulk_options().map(|options| options.transport), cli.transport) {
+        (Some(BulkTransport::Uni), _) | (None, Transport::Uni) => "uni",
+        (Some(BulkTransport::Datagram), _) | (None, Transport::Datagram) => "datagram",
+        _ => "bi",
+    }
+}
+
+fn load_identity(cert: &Path,
+                 key: &Path,
+                 password_file: Option<&Path>) -> Result<ClientIdentity, ClientError> {
+    let password = match password_file {
+        Some(path) => match tls::read_password(path) {
+            Ok(password) => Some(password),
+            Err(source) => return Err(ClientError::IdentityFile { path: path.to_path_buf(), source }),
+        },
+        None => None,
+    };
+    ClientIdentity::load(cert, key, password.as_deref())
+}
+
+/// Opens the key log of `cli`, if any, warning that it holds secrets.
+fn key_log(cli: &Cli) -> Result<Option<KeyLog>, ClientError> {
+    let path = match cli.keylog_path() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let key_log = KeyLog::open(&path)?;
+    warn!("writing TLS secrets to {}; anyone who can read it can decrypt the captured traffic.",
+          path.display());
+    Ok(Some(key_log))
+}
+
+/// Logs the TLS secrets and qlog traces of every target of `fleet` where `cli` asks to.
+fn with_traces(mut fleet: Fleet, cli: &Cli) -> Result<Fleet, ClientError> {
+    let key_log = key_log(cli)?;
+    for target in &mut fleet.targets {
+        target.options.key_log = key_log.clone();
+        target.options.qlog_dir = cli.qlog_dir.clone();
+    }
+    Ok(fleet)
+}
+
+/// Completes when the overall deadline of `client` expires, never if it has none.
+async fn overall_expired(client: &PingClient) {
+    match client.overall_deadline() {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => future::pending().await,
+    }
+}
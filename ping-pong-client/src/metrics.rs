@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::errors::ErrorKind;
+
+/// Upper bounds, in seconds, of the buckets of the duration histograms.
+const BUCKETS: [f64; 14] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Probe results of every target, rendered in the Prometheus text exposition format.
+///
+/// Every series is labelled with the `target` URL; ping series also with the
+/// `transport`, and failures with the [`ErrorKind`] of the error.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    targets: Mutex<BTreeMap<String, TargetMetrics>>,
+}
+
+#[derive(Debug, Default)]
+struct TargetMetrics {
+    up: bool,
+    connect_seconds: Histogram,
+    connect_failures: BTreeMap<ErrorKind, u64>,
+    rtt_seconds: BTreeMap<&'static str, Histogram>,
+    ping_failures: BTreeMap<(&'static str, ErrorKind), u64>,
+    datagrams_sent: u64,
+    datagrams_lost: u64,
+    outages: u64,
+    outage_seconds: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let seconds = value.as_secs_f64();
+        for (count, bound) in self.counts.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `target`, so that its series exist before the first probe ends.
+    pub fn add_target(&self, target: &str) {
+        self.update(target, |_| {});
+    }
+
+    /// Records a connection to `target` established in `elapsed`.
+    pub fn connected(&self, target: &str, elapsed: Duration) {
+        self.update(target, |metrics| {
+            metrics.up = true;
+            metrics.connect_seconds.observe(elapsed);
+        });
+    }
+
+    /// Records a failed connection attempt to `target`.
+    pub fn connect_failed(&self, target: &str, kind: ErrorKind) {
+        self.update(target, |metrics| {
+            metrics.up = false;
+            *metrics.connect_failures.entry(kind).or_default() += 1;
+        });
+    }
+
+    /// Records that the connection to `target` was lost or closed.
+    pub fn disconnected(&self, target: &str) {
+        self.update(target, |metrics| metrics.up = false);
+    }
+
+    /// Records a lost connection to `target` re-established after `duration`.
+    pub fn outage(&self, target: &str, duration: Duration) {
+        self.update(target, |metrics| {
+            metrics.outages += 1;
+            metrics.outage_seconds += duration.as_secs_f64();
+        });
+    }
+
+    /// Records a stream ping to `target` answered after `rtt`, or failed with `kind`.
+    pub fn stream_ping(&self, target: &str, result: Result<Duration, ErrorKind>) {
+        self.update(target, |metrics| metrics.ping("bi", result));
+    }
+
+    /// Records a datagram ping to `target`. A datagram without an echo before the
+    /// reply deadline counts as lost.
+    pub fn datagram_ping(&self, target: &str, result: Result<Duration, ErrorKind>) {
+        self.update(target, |metrics| {
+            metrics.datagrams_sent += 1;
+            if result == Err(ErrorKind::Timeout) {
+                metrics.datagrams_lost += 1;
+            }
+            metrics.ping("datagram", result);
+        });
+    }
+
+    /// All series in the Prometheus text exposition format, version 0.0.4.
+    pub fn render(&self) -> String {
+        let targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        header(&mut out, "pingpong_up", "gauge", "Whether the client is connected to the target.");
+        for (target, metrics) in targets.iter() {
+            let _ = writeln!(out, "pingpong_up{{target=\"{}\"}} {}", escape(target), metrics.up as u8);
+        }
+
+        header(&mut out, "pingpong_connect_duration_seconds", "histogram",
+               "Time to establish the WebTransport session, handshake included.");
+        for (target, metrics) in targets.iter() {
+            histogram(&mut out, "pingpong_connect_duration_seconds",
+                      &format!("target=\"{}\"", escape(target)), &metrics.connect_seconds);
+        }
+
+        header(&mut out, "pingpong_connect_failures_total", "counter", "Failed connection attempts by error kind.");
+        for (target, metrics) in targets.iter() {
+            for (kind, count) in &metrics.connect_failures {
+                let _ = writeln!(out, "pingpong_connect_failures_total{{target=\"{}\",kind=\"{}\"}} {}",
+                                 escape(target), kind, count);
+            }
+        }
+
+        header(&mut out, "pingpong_rtt_seconds", "histogram", "Round-trip time of answered pings.");
+        for (target, metrics) in targets.iter() {
+            for (transport, rtt) in &metrics.rtt_seconds {
+                histogram(&mut out, "pingpong_rtt_seconds",
+                          &format!("target=\"{}\",transport=\"{}\"", escape(target), transport), rtt);
+            }
+        }
+
+        header(&mut out, "pingpong_ping_successes_total", "counter", "Answered pings.");
+        for (target, metrics) in targets.iter() {
+            for (transport, rtt) in &metrics.rtt_seconds {
+                let _ = writeln!(out, "pingpong_ping_successes_total{{target=\"{}\",transport=\"{}\"}} {}",
+                                 escape(target), transport, rtt.count);
+            }
+        }
+
+        header(&mut out, "pingpong_ping_failures_total", "counter", "Failed pings by error kind.");
+        for (target, metrics) in targets.iter() {
+            for ((transport, kind), count) in &metrics.ping_failures {
+                let _ = writeln!(out, "pingpong_ping_failures_total{{target=\"{}\",transport=\"{}\",kind=\"{}\"}} {}",
+                                 escape(target), transport, kind, count);
+            }
+        }
+
+        header(&mut out, "pingpong_datagrams_sent_total", "counter", "Datagram pings sent.");
+        for (target, metrics) in targets.iter() {
+            let _ = writeln!(out, "pingpong_datagrams_sent_total{{target=\"{}\"}} {}",
+                             escape(target), metrics.datagrams_sent);
+        }
+
+        header(&mut out, "pingpong_datagrams_lost_total", "counter", "Datagram pings never echoed.");
+        for (target, metrics) in targets.iter() {
+            let _ = writeln!(out, "pingpong_datagrams_lost_total{{target=\"{}\"}} {}",
+                             escape(target), metrics.datagrams_lost);
+        }
+
+        header(&mut out, "pingpong_outages_total", "counter", "Lost connections that were re-established.");
+        for (target, metrics) in targets.iter() {
+            let _ = writeln!(out, "pingpong_outages_total{{target=\"{}\"}} {}", escape(target), metrics.outages);
+        }
+
+        header(&mut out, "pingpong_outage_seconds_total", "counter",
+               "Time from losing a connection until it was re-established.");
+        for (target, metrics) in targets.iter() {
+            let _ = writeln!(out, "pingpong_outage_seconds_total{{target=\"{}\"}} {}",
+                             escape(target), metrics.outage_seconds);
+        }
+        out
+    }
+
+    fn update(&self, target: &str, f: impl FnOnce(&mut TargetMetrics)) {
+        let mut targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+        f(targets.entry(target.to_string()).or_default());
+    }
+}
+
+impl TargetMetrics {
+    fn ping(&mut self, transport: &'static str, result: Result<Duration, ErrorKind>) {
+        let rtt = self.rtt_seconds.entry(transport).or_default();
+        match result {
+            Ok(elapsed) => rtt.observe(elapsed),
+            Err(kind) => *self.ping_failures.entry((transport, kind)).or_default() += 1,
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    for (bound, count) in BUCKETS.iter().zip(histogram.counts) {
+        let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count);
+    }
+    let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, histogram.count);
+    let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+    let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+}
+
+/// Escapes a label value: backslash, double quote and line feed.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_series_per_target() {
+        let metrics = Metrics::new();
+        let target = "https://example.com:4433/";
+        metrics.connected(target, Duration::from_millis(30));
+        metrics.stream_ping(target, Ok(Duration::from_millis(2)));
+        metrics.stream_ping(target, Err(ErrorKind::Transport));
+        metrics.datagram_ping(target, Ok(Duration::from_millis(1)));
+        metrics.datagram_ping(target, Err(ErrorKind::Timeout));
+        metrics.outage(target, Duration::from_millis(1500));
+
+        let text = metrics.render();
+        let target = r#"target="https://example.com:4433/""#;
+        assert!(text.contains(&format!("pingpong_up{{{}}} 1\n", target)));
+        assert!(text.contains(&format!("pingpong_connect_duration_seconds_bucket{{{},le=\"0.025\"}} 0\n", target)));
+        assert!(text.contains(&format!("pingpong_connect_duration_seconds_bucket{{{},le=\"0.05\"}} 1\n", target)));
+        assert!(text.contains(&format!("pingpong_rtt_seconds_count{{{},transport=\"bi\"}} 1\n", target)));
+        assert!(text.contains(&format!("pingpong_ping_failures_total{{{},transport=\"bi\",kind=\"transport\"}} 1\n", target)));
+        assert!(text.contains(&format!("pingpong_datagrams_sent_total{{{}}} 2\n", target)));
+        assert!(text.contains(&format!("pingpong_datagrams_lost_total{{{}}} 1\n", target)));
+        assert!(text.contains(&format!("pingpong_outages_total{{{}}} 1\n", target)));
+        assert!(text.contains(&format!("pingpong_outage_seconds_total{{{}}} 1.5\n", target)));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -0,0 +1,906 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use ping_pong_client::fanout::FanoutStats;
+use ping_pong_client::fleet::FleetReport;
+use ping_pong_client::fleet::Health;
+use ping_pong_client::reconnect::Outage;
+use ping_pong_client::reuse::FreshPing;
+use ping_pong_client::reuse::ReuseStats;
+use ping_pong_client::fanout::Round;
+use ping_pong_client::stats::millis;
+use ping_pong_client::stats::RttSummary;
+use ping_pong_client::stats::Stats;
+use ping_pong_client::throughput::BulkReport;
+use ping_pong_client::throughput::Interval;
+use ping_pong_client::ClientError;
+use ping_pong_client::Closed;
+use ping_pong_client::PingClient;
+use ping_pong_client::Pong;
+
+use crate::cli::Format;
+
+/// Outcome of a single ping.
+pub enum Outcome<'a> {
+    /// First reply, in order.
+    Pong(Duration),
+    /// First reply, after the reply to a later ping.
+    Reordered(Duration),
+    /// Another reply to a ping that had already been answered.
+    Duplicate,
+    /// Datagram ping never echoed.
+    Lost,
+    Failed(&'a ClientError),
+}
+
+/// A ping and what came of it.
+pub struct Ping<'a> {
+    pub seq: u64,
+    /// Connection of a fan-out round, counting from 1.
+    pub connection: Option<usize>,
+    pub stream_id: Option<u64>,
+    pub outcome: Outcome<'a>,
+}
+
+/// Writes results to standard output as text, JSON lines or CSV rows.
+///
+/// JSON objects carry a `type` of `connected`, `ping`, `comparison`, `interval`,
+/// `outage`, `closed`, `summary`, `target`, `fleet` or `error`. CSV has one row per
+/// ping, per comparison round, per interval of a throughput test or per target of a
+/// fleet, under a header; outages and the summary go to standard error as text so
+/// that standard output stays a single table.
+pub struct Output {
+    format: Format,
+    target: String,
+    address: Option<SocketAddr>,
+    transport: &'static str,
+    sink: RefCell<Sink>,
+    /// Durations of the outages reported so far.
+    outages: Vec<Duration>,
+}
+
+/// Where the JSON lines or the CSV rows go.
+enum Sink {
+    Lines(Box<dyn Write>),
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+}
+
+impl Output {
+    pub fn new(format: Format, target: &str, transport: &'static str) -> Self {
+        Output::with_writer(format, target, transport, Box::new(io::stdout()))
+    }
+
+    /// Output writing its JSON lines or CSV rows to `writer` instead of standard output.
+    fn with_writer(format: Format, target: &str, transport: &'static str, writer: Box<dyn Write>) -> Self {
+        let sink = match format {
+            Format::Csv => Sink::Csv(Box::new(csv::Writer::from_writer(writer))),
+            _ => Sink::Lines(writer),
+        };
+        Output { format, target: target.to_string(), address: None, transport, sink: RefCell::new(sink), outages: Vec::new() }
+    }
+
+    pub fn connected(&mut self, client: &PingClient) {
+        self.address = Some(client.target().address);
+        match self.format {
+            Format::Text => {
+                if let Some(addr) = client.local_addr() {
+                    println!("Bind address: {}.", addr);
+                }
+                match client.connect_timings().resumed {
+                    true => println!("Connected: {}, resuming the TLS session.", client.target()),
+                    false => println!("Connected: {}.", client.target()),
+                }
+            }
+            Format::Json => self.json(&Event::Connected {
+                timestamp: timestamp(),
+                target: &self.target,
+                address: client.target().address,
+                bind_address: client.local_addr(),
+                resumed: client.connect_timings().resumed,
+            }),
+            Format::Csv => {}
+        }
+    }
+
+    pub fn ping(&self, ping: &Ping) {
+        if let Format::Text = self.format {
+            let address = self.address();
+            match ping.outcome {
+                Outcome::Pong(rtt) => println!("pong from {}: seq={} time={} ms", address, ping.seq, millis(rtt)),
+                Outcome::Reordered(rtt) => {
+                    println!("pong from {}: seq={} time={} ms (reordered)", address, ping.seq, millis(rtt))
+                }
+                Outcome::Duplicate => println!("pong from {}: seq={} (DUP!)", address, ping.seq),
+                Outcome::Lost => {}
+                Outcome::Failed(e) => match (ping.connection, ping.stream_id) {
+                    (Some(connection), Some(id)) => println!("  connection {} stream {}: error: {}", connection, id, e),
+                    (Some(connection), None) => println!("  connection {}: error: {}", connection, e),
+                    (None, _) => println!("no reply from {}: seq={} error: {}", address, ping.seq, e),
+                },
+            }
+            return;
+        }
+
+        let (outcome, rtt, error) = match ping.outcome {
+            Outcome::Pong(rtt) => ("pong", Some(rtt), None),
+            Outcome::Reordered(rtt) => ("reordered", Some(rtt), None),
+            Outcome::Duplicate => ("duplicate", None, None),
+            Outcome::Lost => ("lost", None, None),
+            Outcome::Failed(e) => ("error", None, Some(e)),
+        };
+        let record = PingRecord {
+            timestamp: timestamp(),
+            target: &self.target,
+            address: self.address,
+            transport: self.transport,
+            seq: ping.seq,
+            connection: ping.connection,
+            stream_id: ping.stream_id,
+            outcome,
+            rtt_ms: rtt.map(as_millis),
+            error_kind: error.map(|e| e.kind().as_str()).or(matches!(ping.outcome, Outcome::Lost).then_some("timeout")),
+            error: error.map(ToString::to_string),
+        };
+        self.record(Event::Ping(record));
+    }
+
+    /// Reports that the connection was lost with `cause` and is being re-established.
+    pub fn reconnecting(&self, cause: &ClientError) {
+        self.notice(format!("{}; reconnecting to {}", cause, self.address()));
+    }
+
+    /// Reports how the session ended, in text only if the server closed it first or
+    /// the connection did not finish closing in time.
+    pub fn closed(&self, closed: &Closed) {
+        if let Format::Json = self.format {
+            let (by, code, reason, drained, error) = match closed {
+                Closed::ByClient { code, reason, drained } => {
+                    ("client", Some(u64::from(*code)), Some(reason.as_str()), Some(*drained), None)
+                }
+                Closed::ByServer { code, reason } => ("server", Some(*code), Some(reason.as_str()), None, None),
+                Closed::Lost(e) => ("lost", None, None, None, Some(e.to_string())),
+            };
+            self.json(&Event::Closed { timestamp: timestamp(), target: &self.target, by, code, reason, drained, error });
+            return;
+        }
+        match closed {
+            Closed::ByClient { drained: true, .. } | Closed::Lost(_) => {}
+            Closed::ByClient { drained: false, .. } | Closed::ByServer { .. } => {
+                self.notice(format!("session to {} {}", self.address(), closed))
+            }
+        }
+    }
+
+    /// Reports an outage, ended by a new connection or by giving up with `error`.
+    pub fn outage(&mut self, outage: &Outage, error: Option<&ClientError>) {
+        self.outages.push(outage.duration);
+        let attempts = match outage.attempts {
+            1 => "1 attempt".to_string(),
+            n => format!("{} attempts", n),
+        };
+        if let Format::Json = self.format {
+            self.json(&Event::Outage {
+                timestamp: timestamp(),
+                target: &self.target,
+                started: humantime::format_rfc3339_micros(outage.started).to_string(),
+                duration_s: as_seconds(outage.duration),
+                attempts: outage.attempts,
+                restored: error.is_none(),
+                cause_kind: outage.cause.kind().as_str(),
+                cause: outage.cause.to_string(),
+                error_kind: error.map(|e| e.kind().as_str()),
+                error: error.map(ToString::to_string),
+            });
+            return;
+        }
+        match error {
+            None => self.notice(format!("reconnected after {} s and {}", seconds(outage.duration), attempts)),
+            Some(e) => self.notice(format!("gave up reconnecting after {} s and {}: {}",
+                                           seconds(outage.duration), attempts, e)),
+        }
+    }
+
+    /// Reports the replies of fan-out round `seq`.
+    pub fn round(&self, seq: u64, round: &Round) {
+        if let Format::Text = self.format {
+            match (round.fastest(), round.slowest()) {
+                (Some(fastest), Some(slowest)) => {
+                    println!("{}/{} pongs from {}: round={} time={}..{} ms spread={} ms",
+                             round.received(), round.replies.len(), self.address(), seq,
+                             millis(fastest), millis(slowest), millis(slowest - fastest))
+                }
+                _ => println!("no reply from {}: round={}", self.address(), seq),
+            }
+        }
+        for reply in &round.replies {
+            let outcome = match &reply.result {
+                Ok(rtt) => Outcome::Pong(*rtt),
+                Err(e) => Outcome::Failed(e),
+            };
+            if matches!(self.format, Format::Text) && matches!(outcome, Outcome::Pong(_)) {
+                continue;
+            }
+            self.ping(&Ping { seq, connection: Some(reply.connection + 1), stream_id: reply.stream_id, outcome });
+        }
+    }
+
+    /// Reports round `seq` of a comparison: a ping over a fresh connection, one over
+    /// a connection resuming a TLS session if asked for, then one over the reused
+    /// connection.
+    pub fn comparison(&self,
+                      seq: u64,
+                      fresh: &Result<FreshPing, ClientError>,
+                      resumed: Option<&Result<FreshPing, ClientError>>,
+                      reused: &Result<Pong, ClientError>) {
+        if let Format::Text = self.format {
+            let address = self.address();
+            match fresh {
+                Ok(fresh) => println!("fresh connection to {}: seq={} resolve={} handshake={} session={} ping={} total={} ms",
+                                      address, seq, millis(fresh.timings.resolve), millis(fresh.timings.handshake),
+                                      millis(fresh.timings.session), millis(fresh.rtt), millis(fresh.total())),
+                Err(e) => println!("no reply over a fresh connection to {}: seq={} error: {}", address, seq, e),
+            }
+            match resumed {
+                Some(Ok(resumed)) if resumed.timings.resumed => {
+                    let zero_rtt = match resumed.timings.zero_rtt_allowed {
+                        true => " (0-RTT allowed, not sent)",
+                        false => "",
+                    };
+                    println!("resumed connection to {}: seq={} setup={} ping={} total={} ms{}", address, seq,
+                             millis(resumed.timings.total()), millis(resumed.rtt), millis(resumed.total()), zero_rtt)
+                }
+                Some(Ok(resumed)) if resumed.timings.resumption_offered => {
+                    println!("resumption refused by {}: seq={} setup={} ping={} total={} ms (full handshake)",
+                             address, seq, millis(resumed.timings.total()), millis(resumed.rtt), millis(resumed.total()))
+                }
+                Some(Ok(resumed)) => {
+                    println!("no session to resume with {}: seq={} setup={} ping={} total={} ms (full handshake)",
+                             address, seq, millis(resumed.timings.total()), millis(resumed.rtt), millis(resumed.total()))
+                }
+                Some(Err(e)) => println!("no reply over a resumed connection to {}: seq={} error: {}", address, seq, e),
+                None => {}
+            }
+            match reused {
+                Ok(pong) => println!("pong from {}: seq={} time={} ms (reused)", address, seq, millis(pong.rtt)),
+                Err(e) => println!("no reply from {}: seq={} error: {} (reused)", address, seq, e),
+            }
+            return;
+        }
+
+        let timings = fresh.as_ref().ok().map(|fresh| fresh.timings);
+        let fresh_error = fresh.as_ref().err();
+        let reused_error = reused.as_ref().err();
+        let resumed_ok = resumed.and_then(|resumed| resumed.as_ref().ok());
+        let resumed_error = resumed.and_then(|resumed| resumed.as_ref().err());
+        let record = ComparisonRecord {
+            timestamp: timestamp(),
+            target: &self.target,
+            address: self.address,
+            seq,
+            resolve_ms: timings.map(|timings| as_millis(timings.resolve)),
+            handshake_ms: timings.map(|timings| as_millis(timings.handshake)),
+            session_ms: timings.map(|timings| as_millis(timings.session)),
+            fresh_rtt_ms: fresh.as_ref().ok().map(|fresh| as_millis(fresh.rtt)),
+            fresh_total_ms: fresh.as_ref().ok().map(|fresh| as_millis(fresh.total())),
+            fresh_error_kind: fresh_error.map(|e| e.kind().as_str()),
+            fresh_error: fresh_error.map(ToString::to_string),
+            resumption_offered: resumed_ok.map(|resumed| resumed.timings.resumption_offered),
+            resumption_accepted: resumed_ok.map(|resumed| resumed.timings.resumed),
+            zero_rtt_allowed: resumed_ok.map(|resumed| resumed.timings.zero_rtt_allowed),
+            resumed_setup_ms: resumed_ok.map(|resumed| as_millis(resumed.timings.total())),
+            resumed_rtt_ms: resumed_ok.map(|resumed| as_millis(resumed.rtt)),
+            resumed_total_ms: resumed_ok.map(|resumed| as_millis(resumed.total())),
+            resumed_error_kind: resumed_error.map(|e| e.kind().as_str()),
+            resumed_error: resumed_error.map(ToString::to_string),
+            reused_stream_id: reused.as_ref().ok().map(|pong| pong.stream_id),
+            reused_rtt_ms: reused.as_ref().ok().map(|pong| as_millis(pong.rtt)),
+            reused_error_kind: reused_error.map(|e| e.kind().as_str()),
+            reused_error: reused_error.map(ToString::to_string),
+        };
+        self.record(Event::Comparison(record));
+    }
+
+    pub fn interval(&self, interval: &Interval) {
+        if let Format::Text = self.format {
+            println!("{}", interval);
+            return;
+        }
+        let record = IntervalRecord {
+            timestamp: timestamp(),
+            target: &self.target,
+            transport: self.transport,
+            start_s: as_seconds(interval.start),
+            end_s: as_seconds(interval.end),
+            bytes: interval.bytes,
+            bits_per_second: interval.bits_per_second().round(),
+            lost_packets: interval.lost_packets,
+            congestion_events: interval.congestion_events,
+        };
+        self.record(Event::Interval(record));
+    }
+
+    /// Summary of a ping run, ending in `error` if it failed.
+    pub fn summary(&self, stats: &Stats, error: Option<&ClientError>) {
+        let mut text = format!("--- {} ping statistics ---\n{}", self.target, stats);
+        if let Some(longest) = self.outages.iter().max() {
+            let outages = match self.outages.len() {
+                1 => "1 outage".to_string(),
+                n => format!("{} outages", n),
+            };
+            text += &format!("\n{}, {} s without a connection, longest {} s",
+                             outages, seconds(self.outages.iter().sum()), seconds(*longest));
+        }
+        self.summarize(text, ping_summary(self, stats, None, error));
+    }
+
+    /// Summary of fan-out rounds, ending in `error` if they failed.
+    pub fn fanout_summary(&self, stats: &FanoutStats, error: Option<&ClientError>) {
+        let text = format!("--- {} fan-out statistics ---\n{}", self.target, stats);
+        self.summarize(text, ping_summary(self, stats.all(), Some(stats), error));
+    }
+
+    /// Summary of a comparison of fresh and reused connections, ending in `error` if
+    /// it failed.
+    pub fn reuse_summary(&self, stats: &ReuseStats, error: Option<&ClientError>) {
+        let text = format!("--- {} connection reuse statistics ---\n{}", self.target, stats);
+        let step = |stats: &Stats| stats.rtt_summary().map(|rtt| StepSummary {
+            min_ms: as_millis(rtt.min),
+            avg_ms: as_millis(rtt.avg),
+            max_ms: as_millis(rtt.max),
+            p50_ms: as_millis(rtt.p50),
+            p90_ms: as_millis(rtt.p90),
+            p99_ms: as_millis(rtt.p99),
+        });
+        let summary = Event::ReuseSummary(Box::new(ReuseSummary {
+            timestamp: timestamp(),
+            target: &self.target,
+            address: self.address,
+            rounds: stats.fresh().transmitted(),
+            fresh_received: stats.fresh().received(),
+            reused_received: stats.reused().received(),
+            resolve: step(stats.resolve()),
+            handshake: step(stats.handshake()),
+            session: step(stats.session()),
+            fresh_rtt: step(stats.first_ping()),
+            fresh_total: step(stats.fresh()),
+            resumed_received: stats.resumed().received(),
+            resumptions_offered: stats.resumptions_offered(),
+            resumptions_accepted: stats.resumptions_accepted(),
+            zero_rtt_allowed: stats.zero_rtt_allowed(),
+            resumed_setup: step(stats.resumed_setup()),
+            resumed_total: step(stats.resumed()),
+            reused_rtt: step(stats.reused()),
+            setup_cost_ms: stats.setup_cost().map(as_millis),
+            resumption_saving_ms: stats.resumption_saving().map(as_millis),
+            error_kind: error.map(|e| e.kind().as_str()),
+            error: error.map(ToString::to_string),
+        }));
+        self.summarize(text, summary);
+    }
+
+    pub fn throughput_summary(&self, report: &BulkReport) {
+        let text = format!("--- {} throughput statistics ---\n{}", self.target, report);
+        let summary = Event::ThroughputSummary {
+            timestamp: timestamp(),
+            target: &self.target,
+            address: self.address,
+            transport: self.transport,
+            sent: report.sent,
+            delivered: report.delivered,
+            elapsed_s: as_seconds(report.elapsed),
+            goodput_bits_per_second: report.goodput().round(),
+            lost_packets: report.lost_packets(),
+            congestion_events: report.congestion_events(),
+            stalled_intervals: report.stalls(),
+        };
+        self.summarize(text, summary);
+    }
+
+    /// Reports the health of every target of a fleet, then the verdict.
+    pub fn fleet(&self, report: &FleetReport) {
+        for target in &report.targets {
+            let stats = &target.stats;
+            match self.format {
+                Format::Text => {
+                    print!("{} ({}): {}, {}/{} received", target.target.name, target.target.url,
+                           target.health(), stats.received(), stats.transmitted());
+                    if let Some(rtt) = stats.rtt_summary() {
+                        print!(", rtt min/avg/max = {}/{}/{} ms", millis(rtt.min), millis(rtt.avg), millis(rtt.max));
+                    }
+                    match &target.error {
+                        Some(e) => println!(", error: {}", e),
+                        None => println!(),
+                    }
+                }
+                _ => {
+                    let rtt = stats.rtt_summary();
+                    let record = TargetRecord {
+                        timestamp: timestamp(),
+                        name: &target.target.name,
+                        target: target.target.url.as_str(),
+                        transport: target.target.transport.as_str(),
+                        health: target.health().as_str(),
+                        transmitted: stats.transmitted(),
+                        received: stats.received(),
+                        loss_percent: (stats.loss_percent() * 100.0).round() / 100.0,
+                        rtt_min_ms: rtt.map(|rtt| as_millis(rtt.min)),
+                        rtt_avg_ms: rtt.map(|rtt| as_millis(rtt.avg)),
+                        rtt_max_ms: rtt.map(|rtt| as_millis(rtt.max)),
+                        error_kind: target.error.as_ref().map(|e| e.kind().as_str()),
+                        error: target.error.as_ref().map(ToString::to_string),
+                    };
+                    self.record(Event::Target(record));
+                }
+            }
+        }
+
+        let text = format!("--- fleet of {} targets: {} healthy, {} degraded, {} down: {} ---",
+                           report.targets.len(), report.count(Health::Healthy), report.count(Health::Degraded),
+                           report.count(Health::Down), report.verdict().as_str().to_uppercase());
+        let verdict = Event::Fleet {
+            timestamp: timestamp(),
+            verdict: report.verdict().as_str(),
+            targets: report.targets.len(),
+            healthy: report.count(Health::Healthy),
+            degraded: report.count(Health::Degraded),
+            down: report.count(Health::Down),
+        };
+        self.summarize(text, verdict);
+    }
+
+    /// Reports the error the run ended with; text goes to standard error in every format.
+    pub fn error(&self, error: &ClientError) {
+        eprintln!("ping-pong-client: {}", error);
+        if let Format::Json = self.format {
+            self.json(&Event::Error {
+                timestamp: timestamp(),
+                target: &self.target,
+                error_kind: error.kind().as_str(),
+                exit_code: error.kind().exit_code(),
+                error: error.to_string(),
+            });
+        }
+    }
+
+    /// Writes a line of text between the results: to standard output as text, to
+    /// standard error under CSV, nowhere under JSON.
+    fn notice(&self, text: String) {
+        match self.format {
+            Format::Text => println!("{}", text),
+            Format::Json => {}
+            Format::Csv => {
+                self.flush();
+                eprintln!("{}", text);
+            }
+        }
+    }
+
+    fn summarize(&self, text: String, summary: Event) {
+        match self.format {
+            Format::Text => println!("{}", text),
+            Format::Json => self.json(&summary),
+            Format::Csv => {
+                self.flush();
+                eprintln!("{}", text);
+            }
+        }
+    }
+
+    fn record(&self, event: Event) {
+        match (&mut *self.sink.borrow_mut(), event) {
+            (Sink::Csv(csv), Event::Ping(record)) => write_csv(csv, &record),
+            (Sink::Csv(csv), Event::Comparison(record)) => write_csv(csv, &record),
+            (Sink::Csv(csv), Event::Interval(record)) => write_csv(csv, &record),
+            (Sink::Csv(csv), Event::Target(record)) => write_csv(csv, &record),
+            (Sink::Csv(_), _) => {}
+            (Sink::Lines(out), event) => write_json(out, &event),
+        }
+    }
+
+    fn json(&self, event: &Event) {
+        if let Sink::Lines(out) = &mut *self.sink.borrow_mut() {
+            write_json(out, event);
+        }
+    }
+
+    fn flush(&self) {
+        let flushed = match &mut *self.sink.borrow_mut() {
+            Sink::Lines(out) => out.flush(),
+            Sink::Csv(csv) => csv.flush(),
+        };
+        if let Err(e) = flushed {
+            eprintln!("ping-pong-client: cannot write a result: {}", e);
+        }
+    }
+
+    fn address(&self) -> String {
+        self.address.map_or_else(|| "?".to_string(), |address| address.to_string())
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event<'a> {
+    Connected {
+        timestamp: String,
+        target: &'a str,
+        address: SocketAddr,
+        bind_address: Option<SocketAddr>,
+        /// Whether the TLS session of an earlier connection was resumed.
+        resumed: bool,
+    },
+    Ping(PingRecord<'a>),
+    Comparison(ComparisonRecord<'a>),
+    Interval(IntervalRecord<'a>),
+    Outage {
+        timestamp: String,
+        target: &'a str,
+        started: String,
+        duration_s: f64,
+        attempts: u32,
+        /// Whether a new connection was established; `error` tells why not.
+        restored: bool,
+        cause_kind: &'static str,
+        cause: String,
+        error_kind: Option<&'static str>,
+        error: Option<String>,
+    },
+    Closed {
+        timestamp: String,
+        target: &'a str,
+        /// `client`, `server`, or `lost` if neither closed the session.
+        by: &'static str,
+        code: Option<u64>,
+        reason: Option<&'a str>,
+        /// Whether the connection finished closing within the close deadline.
+        drained: Option<bool>,
+        error: Option<String>,
+    },
+    #[serde(rename = "summary")]
+    PingSummary(PingSummary<'a>),
+    #[serde(rename = "summary")]
+    ThroughputSummary {
+        timestamp: String,
+        target: &'a str,
+        address: Option<SocketAddr>,
+        transport: &'static str,
+        sent: u64,
+        delivered: u64,
+        elapsed_s: f64,
+        goodput_bits_per_second: f64,
+        lost_packets: u64,
+        congestion_events: u64,
+        stalled_intervals: usize,
+    },
+    #[serde(rename = "summary")]
+    ReuseSummary(Box<ReuseSummary<'a>>),
+    Target(TargetRecord<'a>),
+    Fleet {
+        timestamp: String,
+        verdict: &'static str,
+        targets: usize,
+        healthy: usize,
+        degraded: usize,
+        down: usize,
+    },
+    Error {
+        timestamp: String,
+        target: &'a str,
+        error_kind: &'static str,
+        exit_code: u8,
+        error: String,
+    },
+}
+
+#[derive(Serialize)]
+struct PingRecord<'a> {
+    timestamp: String,
+    target: &'a str,
+    address: Option<SocketAddr>,
+    transport: &'static str,
+    seq: u64,
+    connection: Option<usize>,
+    stream_id: Option<u64>,
+    /// `pong`, `reordered`, `duplicate`, `lost` or `error`.
+    outcome: &'static str,
+    rtt_ms: Option<f64>,
+    error_kind: Option<&'static str>,
+    error: Option<String>,
+}
+
+/// A round of a comparison; the `fresh_` and step fields are of the fresh connection
+/// with a full handshake, the `resumed_` ones of the connection resuming a TLS
+/// session, empty without `--resume`, the `reused_` ones of the reused connection.
+#[derive(Serialize)]
+struct ComparisonRecord<'a> {
+    timestamp: String,
+    target: &'a str,
+    address: Option<SocketAddr>,
+    seq: u64,
+    resolve_ms: Option<f64>,
+    handshake_ms: Option<f64>,
+    session_ms: Option<f64>,
+    fresh_rtt_ms: Option<f64>,
+    fresh_total_ms: Option<f64>,
+    fresh_error_kind: Option<&'static str>,
+    fresh_error: Option<String>,
+    resumption_offered: Option<bool>,
+    resumption_accepted: Option<bool>,
+    zero_rtt_allowed: Option<bool>,
+    resumed_setup_ms: Option<f64>,
+    resumed_rtt_ms: Option<f64>,
+    resumed_total_ms: Option<f64>,
+    resumed_error_kind: Option<&'static str>,
+    resumed_error: Option<String>,
+    reused_stream_id: Option<u64>,
+    reused_rtt_ms: Option<f64>,
+    reused_error_kind: Option<&'static str>,
+    reused_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ReuseSummary<'a> {
+    timestamp: String,
+    target: &'a str,
+    address: Option<SocketAddr>,
+    rounds: u64,
+    fresh_received: u64,
+    reused_received: u64,
+    resolve: Option<StepSummary>,
+    handshake: Option<StepSummary>,
+    session: Option<StepSummary>,
+    fresh_rtt: Option<StepSummary>,
+    fresh_total: Option<StepSummary>,
+    resumed_received: u64,
+    resumptions_offered: u64,
+    resumptions_accepted: u64,
+    zero_rtt_allowed: u64,
+    resumed_setup: Option<StepSummary>,
+    resumed_total: Option<StepSummary>,
+    reused_rtt: Option<StepSummary>,
+    setup_cost_ms: Option<f64>,
+    resumption_saving_ms: Option<f64>,
+    error_kind: Option<&'static str>,
+    error: Option<String>,
+}
+
+/// Durations of one step over the rounds of a comparison.
+#[derive(Serialize)]
+struct StepSummary {
+    min_ms: f64,
+    avg_ms: f64,
+    max_ms: f64,
+    p50_ms: f64,
+    p90_ms: f64,
+    p99_ms: f64,
+}
+
+#[derive(Serialize)]
+struct IntervalRecord<'a> {
+    timestamp: String,
+    target: &'a str,
+    transport: &'static str,
+    start_s: f64,
+    end_s: f64,
+    bytes: u64,
+    bits_per_second: f64,
+    lost_packets: u64,
+    congestion_events: u64,
+}
+
+#[derive(Serialize)]
+struct TargetRecord<'a> {
+    timestamp: String,
+    name: &'a str,
+    target: &'a str,
+    transport: &'static str,
+    /// `healthy`, `degraded` or `down`.
+    health: &'static str,
+    transmitted: u64,
+    received: u64,
+    loss_percent: f64,
+    rtt_min_ms: Option<f64>,
+    rtt_avg_ms: Option<f64>,
+    rtt_max_ms: Option<f64>,
+    error_kind: Option<&'static str>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PingSummary<'a> {
+    timestamp: String,
+    target: &'a str,
+    address: Option<SocketAddr>,
+    transport: &'static str,
+    transmitted: u64,
+    received: u64,
+    loss_percent: f64,
+    duplicates: u64,
+    reordered: u64,
+    rtt_min_ms: Option<f64>,
+    rtt_avg_ms: Option<f64>,
+    rtt_max_ms: Option<f64>,
+    rtt_stddev_ms: Option<f64>,
+    rtt_p50_ms: Option<f64>,
+    rtt_p90_ms: Option<f64>,
+    rtt_p99_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    median_slowdown: Option<f64>,
+    outages: usize,
+    outage_s: f64,
+    error_kind: Option<&'static str>,
+    error: Option<String>,
+}
+
+fn ping_summary<'a>(output: &'a Output,
+                    stats: &Stats,
+                    fanout: Option<&FanoutStats>,
+                    error: Option<&ClientError>) -> Event<'a> {
+    let rtt = stats.rtt_summary();
+    let ms = |pick: fn(&RttSummary) -> Duration| rtt.as_ref().map(|rtt| as_millis(pick(rtt)));
+    Event::PingSummary(PingSummary {
+        timestamp: timestamp(),
+        target: &output.target,
+        address: output.address,
+        transport: output.transport,
+        transmitted: stats.transmitted(),
+        received: stats.received(),
+        loss_percent: (stats.loss_percent() * 100.0).round() / 100.0,
+        duplicates: stats.duplicates(),
+        reordered: stats.reordered(),
+        rtt_min_ms: ms(|rtt| rtt.min),
+        rtt_avg_ms: ms(|rtt| rtt.avg),
+        rtt_max_ms: ms(|rtt| rtt.max),
+        rtt_stddev_ms: ms(|rtt| rtt.stddev),
+        rtt_p50_ms: ms(|rtt| rtt.p50),
+        rtt_p90_ms: ms(|rtt| rtt.p90),
+        rtt_p99_ms: ms(|rtt| rtt.p99),
+        median_slowdown: fanout.and_then(FanoutStats::median_slowdown),
+        outages: output.outages.len(),
+        outage_s: as_seconds(output.outages.iter().sum()),
+        error_kind: error.map(|e| e.kind().as_str()),
+        error: error.map(ToString::to_string),
+    })
+}
+
+fn write_csv<T: Serialize>(csv: &mut csv::Writer<Box<dyn Write>>, record: &T) {
+    if let Err(e) = csv.serialize(record) {
+        eprintln!("ping-pong-client: cannot write a result: {}", e);
+    }
+}
+
+fn write_json(out: &mut Box<dyn Write>, event: &Event) {
+    let line = match serde_json::to_string(event) {
+        Ok(line) => line,
+        Err(e) => return eprintln!("ping-pong-client: cannot encode a result: {}", e),
+    };
+    if let Err(e) = writeln!(out, "{}", line) {
+        eprintln!("ping-pong-client: cannot write a result: {}", e);
+    }
+}
+
+/// Milliseconds to the microsecond, the resolution of the text output.
+fn as_millis(d: Duration) -> f64 {
+    d.as_micros() as f64 / 1000.0
+}
+
+/// Seconds to the millisecond.
+fn as_seconds(d: Duration) -> f64 {
+    d.as_millis() as f64 / 1000.0
+}
+
+/// Seconds to the millisecond, as text.
+fn seconds(d: Duration) -> String {
+    format!("{:.3}", d.as_secs_f64())
+}
+
+/// Current time in RFC 3339, UTC, to the microsecond.
+fn timestamp() -> String {
+    humantime::format_rfc3339_micros(SystemTime::now()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use ping_pong_client::timeouts::Phase;
+    use serde_json::Value;
+
+    use super::*;
+
+    /// Writer whose bytes stay readable once the output owns it.
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writes a ping, an outage and the summary in `format`, returning what went to
+    /// the writer.
+    fn write_run(format: Format) -> String {
+        let buffer = Buffer::default();
+        let mut output = Output::with_writer(format, "https://example.com:4433/", "bi", Box::new(buffer.clone()));
+        output.address = Some("[::1]:4433".parse().unwrap());
+        let rtt = Duration::from_micros(1500);
+        output.ping(&Ping { seq: 1, connection: None, stream_id: Some(0), outcome: Outcome::Pong(rtt) });
+        let outage = Outage {
+            started: SystemTime::UNIX_EPOCH,
+            duration: Duration::from_millis(2500),
+            cause: ClientError::TimeOut(Phase::Reply),
+            attempts: 2,
+        };
+        output.outage(&outage, None);
+        let mut stats = Stats::new();
+        stats.record_sent();
+        stats.record_reply(rtt);
+        output.summary(&stats, None);
+        drop(output);
+        String::from_utf8(buffer.0.take()).unwrap()
+    }
+
+    /// Keys of `line`, sorted, as their order depends on the features of serde_json.
+    fn keys(line: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = line.as_object().unwrap().keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn json_lines_keep_their_types_and_fields() {
+        let lines: Vec<Value> = write_run(Format::Json).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["type"], "ping");
+        assert_eq!(keys(&lines[0]), ["address", "connection", "error", "error_kind", "outcome", "rtt_ms", "seq",
+                                     "stream_id", "target", "timestamp", "transport", "type"]);
+        assert_eq!(lines[0]["outcome"], "pong");
+        assert_eq!(lines[0]["rtt_ms"], 1.5);
+
+        assert_eq!(lines[1]["type"], "outage");
+        assert_eq!(keys(&lines[1]), ["attempts", "cause", "cause_kind", "duration_s", "error", "error_kind",
+                                     "restored", "started", "target", "timestamp", "type"]);
+        assert_eq!(lines[1]["started"], "1970-01-01T00:00:00.000000Z");
+        assert_eq!(lines[1]["duration_s"], 2.5);
+        assert_eq!(lines[1]["cause_kind"], "timeout");
+
+        assert_eq!(lines[2]["type"], "summary");
+        assert_eq!(keys(&lines[2]), ["address", "duplicates", "error", "error_kind", "loss_percent", "outage_s",
+                                     "outages", "received", "reordered", "rtt_avg_ms", "rtt_max_ms", "rtt_min_ms",
+                                     "rtt_p50_ms", "rtt_p90_ms", "rtt_p99_ms", "rtt_stddev_ms", "target",
+                                     "timestamp", "transmitted", "transport", "type"]);
+        assert_eq!(lines[2]["outages"], 1);
+        assert_eq!(lines[2]["outage_s"], 2.5);
+    }
+
+    #[test]
+    fn csv_has_a_header_and_a_row_per_ping() {
+        let text = write_run(Format::Csv);
+        let rows: Vec<&str> = text.lines().collect();
+
+        assert_eq!(rows.len(), 2, "outages and the summary go to standard error");
+        assert_eq!(rows[0], "timestamp,target,address,transport,seq,connection,stream_id,outcome,rtt_ms,error_kind,error");
+        let (timestamp, rest) = rows[1].split_once(',').unwrap();
+        assert!(timestamp.ends_with('Z'));
+        assert_eq!(rest, "https://example.com:4433/,[::1]:4433,bi,1,,0,pong,1.5,,");
+    }
+}
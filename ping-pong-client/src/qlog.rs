@@ -0,0 +1,286 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use quinn::ConnectionId;
+use quinn::QlogConfig;
+use quinn::QlogStream;
+use serde_json::json;
+use serde_json::Value;
+use wtransport::Connection;
+
+use crate::errors::ClientError;
+
+/// Who closed a traced connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Owner {
+    Local,
+    Remote,
+}
+
+/// Length of the connection ID the client picks for its first packets.
+const CONNECTION_ID_LEN: usize = 8;
+
+/// qlog trace of one connection, written as JSON-SEQ (`.sqlog`) for qvis.
+///
+/// Holds the events the client sees: the connection starting, the server certificate,
+/// the WebTransport session, data moved on streams and in datagrams, packet counts
+/// and the close, along with the packets sent, received and lost and the recovery
+/// metrics that quinn traces. The file is named after the destination connection ID
+/// of the client's first packets, which the server names its trace after as well.
+#[derive(Debug)]
+pub(crate) struct Trace {
+    path: PathBuf,
+    connection_id: ConnectionId,
+    file: Arc<Mutex<File>>,
+    start: Instant,
+}
+
+impl Trace {
+    /// Creates the trace of a connection started at `start` in `dir`, creating the
+    /// directory if needed, and picks the connection ID it is named after.
+    pub(crate) fn create(dir: &Path, start: Instant) -> Result<Self, ClientError> {
+        let mut bytes = [0; CONNECTION_ID_LEN];
+        fastrand::fill(&mut bytes);
+        let connection_id = ConnectionId::new(&bytes);
+
+        let reference = SystemTime::now() - start.elapsed();
+        let reference_ms = reference.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let path = dir.join(format!("{}-client.sqlog", connection_id));
+        let file = match fs::create_dir_all(dir).and_then(|()| File::create(&path)) {
+            Ok(file) => file,
+            Err(source) => return Err(ClientError::QlogFile { path, source }),
+        };
+        let trace = Trace { path, connection_id, file: Arc::new(Mutex::new(file)), start };
+
+        trace.write(&json!({
+            "qlog_version": "0.3",
+            "qlog_format": "JSON-SEQ",
+            "title": "ping-pong-client",
+            "trace": {
+                "vantage_point": { "name": "ping-pong-client", "type": "client" },
+                "common_fields": {
+                    "protocol_type": ["QUIC", "HTTP3", "WebTransport"],
+                    "time_format": "relative",
+                    "reference_time": reference_ms as u64,
+                    "group_id": connection_id.to_string(),
+                },
+            },
+        }));
+        Ok(trace)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Destination connection ID the client has to use for its first packets.
+    pub(crate) fn connection_id(&self) -> ConnectionId {
+        self.connection_id
+    }
+
+    /// Stream quinn writes the packet-level events of the connection to, into this trace.
+    pub(crate) fn packet_events(&self) -> Option<QlogStream> {
+        let mut config = QlogConfig::default();
+        config.writer(Box::new(PacketEvents { file: self.file.clone(), pending: Vec::new(), header_skipped: false }))
+            .start_time(self.start);
+        config.into_stream()
+    }
+
+    /// Records the connection starting from `local` to `remote`.
+    pub(crate) fn started(&self, local: SocketAddr, remote: SocketAddr) {
+        self.event_at(Duration::ZERO, "connectivity:connection_started", json!({
+            "ip_version": if remote.is_ipv4() { "ipv4" } else { "ipv6" },
+            "src_ip": local.ip().to_string(),
+            "src_port": local.port(),
+            "dst_ip": remote.ip().to_string(),
+            "dst_port": remote.port(),
+        }));
+    }
+
+    /// Records the server certificate being verified, `at` after the start of connecting.
+    pub(crate) fn certificate_verified(&self, at: Duration) {
+        self.event_at(at, "security:server_certificate_verified", json!({}));
+    }
+
+    /// Records the handshake as complete and the WebTransport session as accepted,
+    /// `at` after the start of connecting.
+    pub(crate) fn session_established(&self, at: Duration, resumed: bool) {
+        self.event_at(at, "connectivity:connection_state_updated", json!({ "new": "handshake_complete" }));
+        self.event_at(at, "webtransport:session_established", json!({ "resumed": resumed }));
+    }
+
+    /// Records `length` bytes handed to the transport on stream `stream_id`.
+    pub(crate) fn stream_sent(&self, stream_id: u64, length: usize) {
+        self.stream_data_moved(stream_id, length, "application", "transport");
+    }
+
+    /// Records `length` bytes read from the transport on stream `stream_id`.
+    pub(crate) fn stream_received(&self, stream_id: u64, length: usize) {
+        self.stream_data_moved(stream_id, length, "transport", "application");
+    }
+
+    pub(crate) fn datagram_sent(&self, length: usize) {
+        self.event("transport:datagram_data_moved", json!({ "length": length, "from": "application", "to": "transport" }));
+    }
+
+    pub(crate) fn datagram_received(&self, length: usize) {
+        self.event("transport:datagram_data_moved", json!({ "length": length, "from": "transport", "to": "application" }));
+    }
+
+    /// Records the close of `connection` with its packet and datagram counts.
+    pub(crate) fn closed(&self, connection: &Connection, owner: Owner, code: u64, reason: &str) {
+        let stats = connection.quic_connection().stats();
+        self.event("transport:packets_summary", json!({
+            "packets_sent": stats.path.sent_packets,
+            "packets_lost": stats.path.lost_packets,
+            "bytes_lost": stats.path.lost_bytes,
+            "udp_datagrams_sent": stats.udp_tx.datagrams,
+            "udp_bytes_sent": stats.udp_tx.bytes,
+            "udp_datagrams_received": stats.udp_rx.datagrams,
+            "udp_bytes_received": stats.udp_rx.bytes,
+            "congestion_events": stats.path.congestion_events,
+        }));
+        self.event("connectivity:connection_closed", json!({
+            "owner": match owner {
+                Owner::Local => "local",
+                Owner::Remote => "remote",
+            },
+            "application_code": code,
+            "reason": reason,
+        }));
+    }
+
+    fn stream_data_moved(&self, stream_id: u64, length: usize, from: &str, to: &str) {
+        self.event("transport:stream_data_moved", json!({
+            "stream_id": stream_id,
+            "length": length,
+            "from": from,
+            "to": to,
+        }));
+    }
+
+    fn event(&self, name: &str, data: Value) {
+        self.event_at(self.start.elapsed(), name, data);
+    }
+
+    fn event_at(&self, at: Duration, name: &str, data: Value) {
+        self.write(&json!({ "time": millis(at), "name": name, "data": data }));
+    }
+
+    fn write(&self, record: &Value) {
+        append(&self.file, record);
+    }
+}
+
+/// Writer quinn's qlog stream writes to, appending its events to the trace file.
+///
+/// The header quinn starts with is dropped, as the trace has its own, and so is the
+/// `group_id` of each event: quinn gives the server's connection ID once it knows it,
+/// while the trace is grouped under the one it is named after.
+struct PacketEvents {
+    file: Arc<Mutex<File>>,
+    pending: Vec<u8>,
+    header_skipped: bool,
+}
+
+impl Write for PacketEvents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+            let record = self.pending.drain(..=end).collect::<Vec<_>>();
+            if !self.header_skipped {
+                self.header_skipped = true;
+                continue;
+            }
+            let record = record.strip_prefix(b"\x1e").unwrap_or(&record);
+            let mut event = match serde_json::from_slice::<Value>(record) {
+                Ok(event) => event,
+                Err(_) => continue,
+            };
+            if let Some(fields) = event.as_object_mut() {
+                fields.remove("group_id");
+            }
+            append(&self.file, &event);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Appends `record` with the JSON-SEQ record separator. A record that cannot be
+/// written only leaves a gap in the trace.
+fn append(file: &Mutex<File>, record: &Value) {
+    let line = format!("\x1e{}\n", record);
+    let mut file = file.lock().expect("qlog trace poisoned");
+    let _ = file.write_all(line.as_bytes());
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(trace: &Trace) -> Vec<Value> {
+        let text = fs::read_to_string(trace.path()).unwrap();
+        text.split('\x1e')
+            .filter(|record| !record.is_empty())
+            .map(|record| serde_json::from_str(record).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn writes_json_seq_records_relative_to_the_start() {
+        let dir = std::env::temp_dir().join(format!("ping-pong-qlog-{}", std::process::id()));
+        let local: SocketAddr = "[::1]:50000".parse().unwrap();
+        let remote: SocketAddr = "[::1]:4433".parse().unwrap();
+        let trace = Trace::create(&dir, Instant::now()).unwrap();
+        trace.started(local, remote);
+        trace.certificate_verified(Duration::from_millis(3));
+        trace.stream_sent(0, 4);
+
+        let records = records(&trace);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let connection_id = trace.connection_id().to_string();
+        assert_eq!(connection_id.len(), 2 * CONNECTION_ID_LEN);
+        assert!(trace.path().ends_with(format!("{}-client.sqlog", connection_id)));
+        assert_eq!(records[0]["qlog_format"], "JSON-SEQ");
+        assert_eq!(records[0]["trace"]["common_fields"]["group_id"], connection_id);
+        assert_eq!(records[1]["name"], "connectivity:connection_started");
+        assert_eq!(records[1]["data"]["dst_port"], 4433);
+        assert_eq!(records[2]["time"], 3.0);
+        assert_eq!(records[3]["data"]["length"], 4);
+    }
+
+    #[test]
+    fn takes_the_events_of_quinn_without_its_header() {
+        let dir = std::env::temp_dir().join(format!("ping-pong-qlog-packets-{}", std::process::id()));
+        let trace = Trace::create(&dir, Instant::now()).unwrap();
+        let mut events = PacketEvents { file: trace.file.clone(), pending: Vec::new(), header_skipped: false };
+        events.write_all(b"\x1e{\"qlog_version\":\"0.3\"}\n\x1e{\"time\":1.5,\"name\":\"transport:packet_sent\",").unwrap();
+        events.write_all(b"\"group_id\":\"84fe7894\"}\n").unwrap();
+
+        let records = records(&trace);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1], json!({ "time": 1.5, "name": "transport:packet_sent" }));
+    }
+}
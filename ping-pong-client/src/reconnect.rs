@@ -0,0 +1,136 @@
+use std::time::Duration;
+use std::time::SystemTime;
+
+use tokio::time;
+use tokio::time::Instant;
+use url::Url;
+
+use crate::errors::ClientError;
+use crate::errors::ErrorKind;
+use crate::timeouts;
+use crate::timeouts::Phase;
+use crate::ClientOptions;
+use crate::PingClient;
+
+/// Delays between the attempts to re-establish a lost connection.
+///
+/// The delay doubles from `initial` with every failed attempt up to `max`, and is
+/// then scaled by a random factor between one half and one, so that clients that
+/// lost their connections together do not all retry at the same instant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// Delay before the first attempt.
+    pub initial: Duration,
+    /// Cap on the delay before any attempt.
+    pub max: Duration,
+    /// Attempts after which reconnecting is given up; unbounded if `None`.
+    pub attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff { initial: Duration::from_millis(500), max: Duration::from_secs(30), attempts: None }
+    }
+}
+
+impl Backoff {
+    /// Upper bound of the delay before attempt `attempt`, counting from 0.
+    pub fn ceiling(&self, attempt: u32) -> Duration {
+        self.initial.saturating_mul(2u32.saturating_pow(attempt)).min(self.max)
+    }
+
+    /// Jittered delay before attempt `attempt`, counting from 0.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.ceiling(attempt).mul_f64(0.5 + fastrand::f64() / 2.0)
+    }
+
+    /// Whether no attempt should follow `attempts` failed ones.
+    pub fn exhausted(&self, attempts: u32) -> bool {
+        self.attempts.is_some_and(|max| attempts >= max)
+    }
+}
+
+/// A lost connection, from the last reply over it until a new connection was
+/// established or reconnecting was given up.
+#[derive(Debug)]
+pub struct Outage {
+    /// When the server last replied over the lost connection.
+    pub started: SystemTime,
+    pub duration: Duration,
+    /// Error the connection was lost with.
+    pub cause: ClientError,
+    /// Connection attempts made.
+    pub attempts: u32,
+}
+
+/// Re-establishes a connection to `url` lost with `cause`, retrying after the delays
+/// of `backoff`. The outage counts from `since`, the last reply over the lost
+/// connection: a silent server is only found gone after the QUIC idle timeout.
+///
+/// Gives up once `backoff` is exhausted, on a local setup error that a retry would
+/// only repeat, or when the `overall` deadline of the lost client expires; that
+/// deadline also bounds the new client. Returns the new client or the last error,
+/// with the outage either way.
+pub async fn reconnect(url: &Url,
+                       options: &ClientOptions,
+                       backoff: &Backoff,
+                       overall: Option<Instant>,
+                       since: Instant,
+                       cause: ClientError) -> (Result<PingClient, ClientError>, Outage) {
+    let started = SystemTime::now() - since.elapsed();
+    let mut attempts = 0;
+
+    let result = loop {
+        let wake = Instant::now() + backoff.delay(attempts);
+        if let Some(deadline) = overall.filter(|deadline| *deadline <= wake) {
+            time::sleep_until(deadline).await;
+            break Err(ClientError::TimeOut(Phase::Overall));
+        }
+        time::sleep_until(wake).await;
+
+        attempts += 1;
+        let mut options = options.clone();
+        options.timeouts.overall = timeouts::remaining(overall);
+        match PingClient::connect(url, &options).await {
+            Ok(client) => break Ok(client),
+            Err(e) if e.kind() == ErrorKind::Local || backoff.exhausted(attempts) => break Err(e),
+            Err(ClientError::TimeOut(Phase::Overall)) => break Err(ClientError::TimeOut(Phase::Overall)),
+            Err(_) => {}
+        }
+    };
+    (result, Outage { started, duration: since.elapsed(), cause, attempts })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let backoff = Backoff { initial: Duration::from_millis(100), max: Duration::from_secs(1), attempts: None };
+
+        assert_eq!(backoff.ceiling(0), Duration::from_millis(100));
+        assert_eq!(backoff.ceiling(3), Duration::from_millis(800));
+        assert_eq!(backoff.ceiling(4), Duration::from_secs(1));
+        assert_eq!(backoff.ceiling(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn delay_is_jittered_below_the_ceiling() {
+        let backoff = Backoff::default();
+        for attempt in 0..20 {
+            let delay = backoff.delay(attempt);
+            assert!(delay <= backoff.ceiling(attempt));
+            assert!(delay >= backoff.ceiling(attempt) / 2);
+        }
+    }
+
+    #[test]
+    fn attempts_cap_reconnecting() {
+        let backoff = Backoff { attempts: Some(3), ..Backoff::default() };
+
+        assert!(!backoff.exhausted(2));
+        assert!(backoff.exhausted(3));
+        assert!(!Backoff::default().exhausted(u32::MAX));
+    }
+}
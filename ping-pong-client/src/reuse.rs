@@ -0,0 +1,311 @@
+use std::fmt;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use url::Url;
+
+use crate::errors::ClientError;
+use crate::stats::millis;
+use crate::stats::Stats;
+use crate::timeouts;
+use crate::ClientOptions;
+use crate::ConnectTimings;
+use crate::PingClient;
+
+/// A ping over a connection established for it alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreshPing {
+    pub timings: ConnectTimings,
+    /// Round-trip time of the first ping over the new connection.
+    pub rtt: Duration,
+}
+
+impl FreshPing {
+    /// Time from the start of connecting until the reply.
+    pub fn total(&self) -> Duration {
+        self.timings.total() + self.rtt
+    }
+}
+
+/// Connects to `url`, pings once over the new connection and closes it.
+///
+/// Nothing is carried over from earlier connections but the TLS sessions of
+/// [`ClientOptions::sessions`]: without them every attempt pays the full handshake.
+/// Never runs past the `overall` deadline.
+pub async fn fresh_ping(url: &Url,
+                        options: &ClientOptions,
+                        overall: Option<Instant>) -> Result<FreshPing, ClientError> {
+    let mut options = options.clone();
+    if overall.is_some() {
+        options.timeouts.overall = timeouts::remaining(overall);
+    }
+    let client = PingClient::connect(url, &options).await?;
+    let pong = client.ping().await;
+    let timings = client.connect_timings();
+    client.close().await;
+    Ok(FreshPing { timings, rtt: pong?.rtt })
+}
+
+/// Setup and ping times over fresh connections, with a full handshake or resuming
+/// a TLS session, next to ping times over a reused one.
+#[derive(Debug, Default)]
+pub struct ReuseStats {
+    resolve: Stats,
+    handshake: Stats,
+    session: Stats,
+    first_ping: Stats,
+    setup: Stats,
+    fresh: Stats,
+    resumed_setup: Stats,
+    resumed: Stats,
+    resumptions_offered: u64,
+    resumptions_accepted: u64,
+    zero_rtt_allowed: u64,
+    reused: Stats,
+}
+
+impl ReuseStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an attempt to ping over a fresh connection with a full handshake,
+    /// `None` if it failed.
+    pub fn record_fresh(&mut self, ping: Option<&FreshPing>) {
+        self.fresh.record_sent();
+        if let Some(ping) = ping {
+            self.fresh.record_reply(ping.total());
+            let steps = [
+                (&mut self.resolve, ping.timings.resolve),
+                (&mut self.handshake, ping.timings.handshake),
+                (&mut self.session, ping.timings.session),
+                (&mut self.first_ping, ping.rtt),
+                (&mut self.setup, ping.timings.total()),
+            ];
+            for (stats, elapsed) in steps {
+                stats.record_sent();
+                stats.record_reply(elapsed);
+            }
+        }
+    }
+
+    /// Records an attempt to ping over a fresh connection resuming a TLS session,
+    /// `None` if it failed. Only the setup of accepted resumptions is recorded.
+    pub fn record_resumed(&mut self, ping: Option<&FreshPing>) {
+        self.resumed.record_sent();
+        if let Some(ping) = ping {
+            self.resumed.record_reply(ping.total());
+            if ping.timings.resumption_offered {
+                self.resumptions_offered += 1;
+            }
+            if ping.timings.zero_rtt_allowed {
+                self.zero_rtt_allowed += 1;
+            }
+            if ping.timings.resumed {
+                self.resumptions_accepted += 1;
+                self.resumed_setup.record_sent();
+                self.resumed_setup.record_reply(ping.timings.total());
+            }
+        }
+    }
+
+    /// Records a ping over the reused connection, `None` if it failed.
+    pub fn record_reused(&mut self, rtt: Option<Duration>) {
+        self.reused.record_sent();
+        if let Some(rtt) = rtt {
+            self.reused.record_reply(rtt);
+        }
+    }
+
+    /// Name resolution and endpoint setup of the fresh connections.
+    pub fn resolve(&self) -> &Stats {
+        &self.resolve
+    }
+
+    /// QUIC and TLS handshakes of the fresh connections, until the server certificate is verified.
+    pub fn handshake(&self) -> &Stats {
+        &self.handshake
+    }
+
+    /// Session requests of the fresh connections, with the end of their handshakes.
+    pub fn session(&self) -> &Stats {
+        &self.session
+    }
+
+    /// First pings over the fresh connections.
+    pub fn first_ping(&self) -> &Stats {
+        &self.first_ping
+    }
+
+    /// Fresh connections from the start of connecting until the reply to their ping.
+    pub fn fresh(&self) -> &Stats {
+        &self.fresh
+    }
+
+    /// Setup of the fresh connections with a full handshake, from resolving until
+    /// the session was accepted.
+    pub fn setup(&self) -> &Stats {
+        &self.setup
+    }
+
+    /// Setup of the fresh connections that resumed a TLS session.
+    pub fn resumed_setup(&self) -> &Stats {
+        &self.resumed_setup
+    }
+
+    /// Fresh connections resuming a TLS session, from the start of connecting until
+    /// the reply to their ping, whether the server accepted the resumption or not.
+    pub fn resumed(&self) -> &Stats {
+        &self.resumed
+    }
+
+    /// Attempts to resume a TLS session that had a session to offer the server.
+    pub fn resumptions_offered(&self) -> u64 {
+        self.resumptions_offered
+    }
+
+    /// Attempts to resume a TLS session that the server accepted.
+    pub fn resumptions_accepted(&self) -> u64 {
+        self.resumptions_accepted
+    }
+
+    /// Attempts offering a session in which the server allowed 0-RTT data, which the
+    /// client does not send.
+    pub fn zero_rtt_allowed(&self) -> u64 {
+        self.zero_rtt_allowed
+    }
+
+    /// How much faster resuming a TLS session sets a connection up than a full
+    /// handshake, by median.
+    pub fn resumption_saving(&self) -> Option<Duration> {
+        let full = self.setup.rtt_summary()?.p50;
+        let resumed = self.resumed_setup.rtt_summary()?.p50;
+        Some(full.saturating_sub(resumed))
+    }
+
+    /// Pings over the reused connection.
+    pub fn reused(&self) -> &Stats {
+        &self.reused
+    }
+
+    /// How much longer a ping takes over a fresh connection than over the reused
+    /// one, by median.
+    pub fn setup_cost(&self) -> Option<Duration> {
+        let fresh = self.fresh.rtt_summary()?.p50;
+        let reused = self.reused.rtt_summary()?.p50;
+        Some(fresh.saturating_sub(reused))
+    }
+}
+
+impl fmt::Display for ReuseStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} fresh connections, {} answered; {} pings over the reused connection, {} answered",
+                 self.fresh.transmitted(), self.fresh.received(), self.reused.transmitted(), self.reused.received())?;
+        let steps = [
+            ("resolve", &self.resolve),
+            ("handshake", &self.handshake),
+            ("session", &self.session),
+            ("first ping", &self.first_ping),
+            ("fresh total", &self.fresh),
+            ("resumed setup", &self.resumed_setup),
+            ("resumed total", &self.resumed),
+            ("reused ping", &self.reused),
+        ];
+        for (name, stats) in steps {
+            if let Some(rtt) = stats.rtt_summary() {
+                writeln!(f, "{:<13} min/avg/max/p50 = {}/{}/{}/{} ms",
+                         name, millis(rtt.min), millis(rtt.avg), millis(rtt.max), millis(rtt.p50))?;
+            }
+        }
+        match (self.fresh.rtt_summary(), self.reused.rtt_summary()) {
+            (Some(fresh), Some(reused)) => {
+                write!(f, "connection setup adds {} ms to a ping: {:.1}x the round trip over the reused connection",
+                       millis(fresh.p50.saturating_sub(reused.p50)), fresh.p50.as_secs_f64() / reused.p50.as_secs_f64())
+            }
+            _ => write!(f, "connection setup cost unknown: no ping answered over both kinds of connection"),
+        }?;
+        if self.resumed.transmitted() > 0 {
+            write!(f, "\nthe server accepted {} of {} session resumptions offered",
+                   self.resumptions_accepted, self.resumptions_offered)?;
+            if let Some(saving) = self.resumption_saving() {
+                write!(f, ", saving {} ms of connection setup", millis(saving))?;
+            }
+            let unoffered = self.resumed.received() - self.resumptions_offered;
+            if unoffered > 0 {
+                write!(f, "\n{} connections had no session to offer", unoffered)?;
+            }
+            if self.zero_rtt_allowed > 0 {
+                write!(f, "\nthe server allowed 0-RTT in {} of the sessions offered, which the client does not send",
+                       self.zero_rtt_allowed)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn fresh(handshake: u64, session: u64, rtt: u64) -> FreshPing {
+        let timings = ConnectTimings {
+            resolve: ms(1),
+            handshake: ms(handshake),
+            session: ms(session),
+            ..ConnectTimings::default()
+        };
+        FreshPing { timings, rtt: ms(rtt) }
+    }
+
+    #[test]
+    fn separates_setup_from_round_trips() {
+        let mut stats = ReuseStats::new();
+        stats.record_fresh(Some(&fresh(4, 2, 3)));
+        stats.record_fresh(None);
+        stats.record_fresh(Some(&fresh(6, 2, 1)));
+        for rtt in [1, 2, 3] {
+            stats.record_reused(Some(ms(rtt)));
+        }
+
+        assert_eq!(stats.fresh().transmitted(), 3);
+        assert_eq!(stats.fresh().received(), 2);
+        assert_eq!(stats.handshake().transmitted(), 2);
+        assert_eq!(stats.handshake().rtt_summary().unwrap().avg, ms(5));
+        assert_eq!(stats.fresh().rtt_summary().unwrap().p50, ms(10));
+        assert_eq!(stats.setup_cost(), Some(ms(8)));
+    }
+
+    #[test]
+    fn resumption_saving_counts_accepted_resumptions_only() {
+        let mut stats = ReuseStats::new();
+        stats.record_fresh(Some(&fresh(4, 2, 1)));
+        let mut resumed = fresh(3, 0, 1);
+        resumed.timings.resumed = true;
+        resumed.timings.resumption_offered = true;
+        stats.record_resumed(Some(&resumed));
+        let mut refused = fresh(4, 2, 1);
+        refused.timings.resumption_offered = true;
+        stats.record_resumed(Some(&refused));
+        stats.record_resumed(Some(&fresh(4, 2, 1)));
+        stats.record_resumed(None);
+
+        assert_eq!(stats.resumed().transmitted(), 4);
+        assert_eq!(stats.resumed().received(), 3);
+        assert_eq!(stats.resumptions_offered(), 2);
+        assert_eq!(stats.resumptions_accepted(), 1);
+        assert_eq!(stats.resumption_saving(), Some(ms(3)));
+    }
+
+    #[test]
+    fn no_cost_without_both_kinds_of_pings() {
+        let mut stats = ReuseStats::new();
+        stats.record_fresh(Some(&fresh(4, 2, 3)));
+        stats.record_reused(None);
+
+        assert_eq!(stats.setup_cost(), None);
+    }
+}
@@ -0,0 +1,78 @@
+use std::future;
+use std::process;
+
+use tokio::signal;
+use tokio::sync::watch;
+use tracing::info;
+
+/// Exit status after a second interrupt, as a shell reports a process killed by SIGINT.
+const INTERRUPTED: i32 = 130;
+
+/// Request to stop, made by the first SIGINT or SIGTERM.
+///
+/// A running mode stops issuing pings once it is requested, lets those in flight
+/// finish, then closes the connection and prints its summary. A second signal
+/// exits at once, for when draining takes too long.
+#[derive(Debug, Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Starts listening for the signals, which no longer kill the process.
+    pub fn listen() -> Self {
+        let (requested, shutdown) = watch::channel(false);
+        tokio::spawn(async move {
+            let mut signals = Signals::new();
+            signals.next().await;
+            info!("stopping after the pings in flight; interrupt again to quit at once");
+            requested.send_replace(true);
+            signals.next().await;
+            process::exit(INTERRUPTED);
+        });
+        Shutdown(shutdown)
+    }
+
+    /// Completes once a stop was requested.
+    pub async fn requested(&mut self) {
+        if self.0.wait_for(|requested| *requested).await.is_err() {
+            future::pending::<()>().await;
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+/// SIGINT and, on Unix, SIGTERM.
+struct Signals {
+    #[cfg(unix)]
+    terminate: Option<signal::unix::Signal>,
+}
+
+impl Signals {
+    fn new() -> Self {
+        Signals {
+            #[cfg(unix)]
+            terminate: signal::unix::signal(signal::unix::SignalKind::terminate()).ok(),
+        }
+    }
+
+    async fn next(&mut self) {
+        #[cfg(unix)]
+        let terminate = async {
+            match &mut self.terminate {
+                Some(terminate) => {
+                    terminate.recv().await;
+                }
+                None => future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = future::pending::<()>();
+
+        tokio::select! {
+            _ = signal::ctrl_c() => {}
+            _ = terminate => {}
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Round-trip statistics collected over a run of pings.
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    transmitted: u64,
+    rtts: Vec<Duration>,
+    duplicates: u64,
+    reordered: u64,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a ping that has been sent.
+    pub fn record_sent(&mut self) {
+        self.transmitted += 1;
+    }
+
+    /// Records a ping that was answered after `rtt`.
+    pub fn record_reply(&mut self, rtt: Duration) {
+        self.rtts.push(rtt);
+    }
+
+    /// Records a reply to a ping that had already been answered.
+    pub fn record_duplicate(&mut self) {
+        self.duplicates += 1;
+    }
+
+    /// Records a reply that arrived after the reply to a later ping.
+    pub fn record_reordered(&mut self) {
+        self.reordered += 1;
+    }
+
+    pub fn transmitted(&self) -> u64 {
+        self.transmitted
+    }
+
+    pub fn received(&self) -> u64 {
+        self.rtts.len() as u64
+    }
+
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    pub fn reordered(&self) -> u64 {
+        self.reordered
+    }
+
+    /// Percentage of transmitted pings without a reply.
+    pub fn loss_percent(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        self.transmitted.saturating_sub(self.received()) as f64 * 100.0 / self.transmitted as f64
+    }
+
+    /// Summary of the recorded round-trip times, `None` until a reply has been recorded.
+    pub fn rtt_summary(&self) -> Option<RttSummary> {
+        if self.rtts.is_empty() {
+            return None;
+        }
+
+        let mut sorted = self.rtts.clone();
+        sorted.sort();
+
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().map(Duration::as_secs_f64).sum::<f64>() / n;
+        let variance = sorted
+            .iter()
+            .map(|rtt| (rtt.as_secs_f64() - mean).powi(2))
+            .sum::<f64>() / n;
+
+        Some(RttSummary {
+            min: sorted[0],
+            avg: Duration::from_secs_f64(mean),
+            max: sorted[sorted.len() - 1],
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+            p50: percentile(&sorted, 50.0),
+            p90: percentile(&sorted, 90.0),
+            p99: percentile(&sorted, 99.0),
+        })
+    }
+}
+
+/// Aggregated round-trip times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RttSummary {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    pub stddev: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+}
+
+/// Nearest-rank percentile of an ascending, non-empty slice.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Formats a duration as fractional milliseconds, the unit `ping(8)` reports in.
+pub fn millis(d: Duration) -> String {
+    format!("{:.3}", d.as_secs_f64() * 1000.0)
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} pings transmitted, {} received, {:.1}% loss",
+               self.transmitted, self.received(), self.loss_percent())?;
+        if self.duplicates > 0 {
+            write!(f, ", {} duplicates", self.duplicates)?;
+        }
+        if self.reordered > 0 {
+            write!(f, ", {} reordered", self.reordered)?;
+        }
+
+        if let Some(rtt) = self.rtt_summary() {
+            write!(f, "\nrtt min/avg/max/stddev = {}/{}/{}/{} ms",
+                   millis(rtt.min), millis(rtt.avg), millis(rtt.max), millis(rtt.stddev))?;
+            write!(f, "\nrtt p50/p90/p99 = {}/{}/{} ms",
+                   millis(rtt.p50), millis(rtt.p90), millis(rtt.p99))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn summary_of_replies() {
+        let mut stats = Stats::new();
+        for n in [4, 2, 8, 6] {
+            stats.record_sent();
+            stats.record_reply(ms(n));
+        }
+
+        let rtt = stats.rtt_summary().unwrap();
+        assert_eq!(rtt.min, ms(2));
+        assert_eq!(rtt.max, ms(8));
+        assert_eq!(rtt.avg, ms(5));
+        assert!((rtt.stddev.as_secs_f64() - 5e-6f64.sqrt()).abs() < 1e-9);
+        assert_eq!(rtt.p50, ms(4));
+        assert_eq!(rtt.p90, ms(8));
+        assert_eq!(rtt.p99, ms(8));
+    }
+
+    #[test]
+    fn loss_without_replies() {
+        let mut stats = Stats::new();
+        stats.record_sent();
+        stats.record_sent();
+
+        assert_eq!(stats.transmitted, 2);
+        assert_eq!(stats.received(), 0);
+        assert_eq!(stats.loss_percent(), 100.0);
+        assert!(stats.rtt_summary().is_none());
+    }
+}
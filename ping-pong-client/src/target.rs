@@ -0,0 +1,123 @@
+use std::fmt;
+use std::future;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use tokio::net::lookup_host;
+use url::Host;
+use url::Url;
+use wtransport::config::DnsLookupFuture;
+use wtransport::config::DnsResolver;
+use wtransport::error::ConnectingError;
+
+use crate::errors::ClientError;
+
+/// Address family restriction applied when resolving the target host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpVersion {
+    #[default]
+    Any,
+    V4,
+    V6,
+}
+
+impl IpVersion {
+    fn matches(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpVersion::Any => true,
+            IpVersion::V4 => addr.is_ipv4(),
+            IpVersion::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+/// A server resolved from the command line.
+#[derive(Debug, Clone)]
+pub struct Target {
+    /// URL of the WebTransport session, with the host set to the TLS server name.
+    pub url: Url,
+    /// Socket address the URL host resolved to.
+    pub address: SocketAddr,
+}
+
+impl Target {
+    /// Resolves the host of `url`, keeping only addresses of the requested family.
+    ///
+    /// When `server_name` is given it replaces the host of the URL, so it is used for
+    /// TLS and the session request, while packets still go to the resolved address.
+    pub async fn resolve(url: &Url,
+                         server_name: Option<&str>,
+                         ip_version: IpVersion) -> Result<Self, ClientError> {
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return Err(ClientError::Connect(ConnectingError::InvalidUrl(url.to_string()))),
+        };
+        let port = url.port_or_known_default().unwrap_or(443);
+
+        let address = match url.host() {
+            Some(Host::Ipv4(ip)) => Some(SocketAddr::new(ip.into(), port)),
+            Some(Host::Ipv6(ip)) => Some(SocketAddr::new(ip.into(), port)),
+            _ => match lookup_host((host, port)).await {
+                Ok(mut addrs) => addrs.find(|addr| ip_version.matches(addr)),
+                Err(source) => {
+                    return Err(ClientError::Dns { host: host.to_string(), source: Some(source) })
+                }
+            },
+        };
+        let address = match address {
+            Some(address) if ip_version.matches(&address) => address,
+            _ => return Err(ClientError::Dns { host: host.to_string(), source: None }),
+        };
+
+        let mut url = url.clone();
+        if let Some(name) = server_name {
+            if url.set_host(Some(name)).is_err() {
+                return Err(ClientError::Connect(ConnectingError::InvalidServerName(name.to_string())));
+            }
+        }
+
+        Ok(Target { url, address })
+    }
+
+    /// Unspecified address of the target's family with an ephemeral port.
+    pub fn default_bind_address(&self) -> SocketAddr {
+        match self.address {
+            SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        }
+    }
+
+    /// DNS resolver answering every lookup with the already resolved address.
+    pub fn resolver(&self) -> StaticResolver {
+        StaticResolver(self.address)
+    }
+}
+
+/// Checks that `name` can replace the URL host as server name: a DNS name, not an IP
+/// address, as TLS and the session request need one.
+pub fn parse_server_name(name: &str) -> Result<String, String> {
+    match Host::parse(name) {
+        Ok(Host::Domain(name)) => Ok(name),
+        Ok(_) => Err("server name must be a DNS name, not an IP address".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.url, self.address)
+    }
+}
+
+/// Resolver that always returns the same address, so the URL host can carry the
+/// TLS server name independently of where the connection is sent.
+#[derive(Debug)]
+pub struct StaticResolver(SocketAddr);
+
+impl DnsResolver for StaticResolver {
+    fn resolve(&self, _host: &str) -> Pin<Box<dyn DnsLookupFuture>> {
+        Box::pin(future::ready(Ok(Some(self.0))))
+    }
+}
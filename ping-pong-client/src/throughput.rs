@@ -0,0 +1,692 @@
+use std::fmt;
+use std::future;
+use std::future::Future;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::task;
+use tokio::task::JoinSet;
+use tokio::time;
+use tokio::time::Instant;
+use tokio::time::MissedTickBehavior;
+use wtransport::Connection;
+use wtransport::RecvStream;
+use wtransport::SendStream;
+
+use crate::client::open_bi;
+use crate::client::open_uni;
+use crate::errors::ClientError;
+use crate::timeouts;
+use crate::timeouts::Phase;
+use crate::timeouts::Timeouts;
+
+/// Header of a stream whose data the server discards.
+pub const SINK: &[u8] = b"sink\n";
+/// Header of a bidirectional stream whose data the server echoes back.
+pub const ECHO: &[u8] = b"echo\n";
+/// Prefix of throughput datagrams, telling their echoes from those of datagram pings.
+const DATAGRAM_PREFIX: &[u8] = b"bulk:";
+
+/// Transport carrying a throughput test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkTransport {
+    /// Bidirectional streams, sunk by the server or echoed with [`BulkOptions::echo`].
+    Bi,
+    /// Unidirectional streams sunk by the server.
+    Uni,
+    /// Datagrams echoed by the server, paced at [`BulkOptions::bitrate`].
+    Datagram,
+}
+
+impl fmt::Display for BulkTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BulkTransport::Bi => f.write_str("bidirectional streams"),
+            BulkTransport::Uni => f.write_str("unidirectional streams"),
+            BulkTransport::Datagram => f.write_str("datagrams"),
+        }
+    }
+}
+
+/// When a throughput test stops sending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// After this many payload bytes, over all streams.
+    Bytes(u64),
+    /// After this long.
+    Duration(Duration),
+}
+
+/// Parameters of a throughput test.
+#[derive(Debug, Clone)]
+pub struct BulkOptions {
+    pub transport: BulkTransport,
+    /// Streams sending in parallel; datagrams always use a single sender.
+    pub streams: usize,
+    pub limit: Limit,
+    /// Bytes per write, or the datagram size capped by what the path allows.
+    pub chunk_size: usize,
+    /// Have bidirectional streams echoed back instead of sunk.
+    pub echo: bool,
+    /// Datagram sending rate in bits per second.
+    pub bitrate: u64,
+    /// Period of the interval reports.
+    pub interval: Duration,
+}
+
+impl Default for BulkOptions {
+    fn default() -> Self {
+        BulkOptions {
+            transport: BulkTransport::Bi,
+            streams: 1,
+            limit: Limit::Duration(Duration::from_secs(10)),
+            chunk_size: 16 * 1024,
+            echo: false,
+            bitrate: 1_000_000,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Data moved during one reporting interval of a throughput test.
+///
+/// Bytes count what was written to sunk streams, or what came back from echoed
+/// streams and datagrams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    /// Offset of the interval from the start of the test.
+    pub start: Duration,
+    pub end: Duration,
+    pub bytes: u64,
+    /// Packets QUIC declared lost, and so retransmitted for streams.
+    pub lost_packets: u64,
+    /// Times the congestion window was reduced.
+    pub congestion_events: u64,
+}
+
+impl Interval {
+    pub fn bits_per_second(&self) -> f64 {
+        bits_per_second(self.bytes, self.end - self.start)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:6.2}-{:<6.2} s  {:>11}  {:>13}  {} lost packets",
+               self.start.as_secs_f64(), self.end.as_secs_f64(),
+               format_bytes(self.bytes), format_bitrate(self.bits_per_second()), self.lost_packets)
+    }
+}
+
+/// Outcome of a throughput test.
+#[derive(Debug, Clone)]
+pub struct BulkReport {
+    pub transport: BulkTransport,
+    /// Payload bytes sent.
+    pub sent: u64,
+    /// Payload bytes the server confirmed: acknowledged on unidirectional streams,
+    /// counted by the server on sunk bidirectional streams, or echoed back.
+    pub delivered: u64,
+    pub elapsed: Duration,
+    pub intervals: Vec<Interval>,
+}
+
+impl BulkReport {
+    /// Delivered bits per second over the whole test.
+    pub fn goodput(&self) -> f64 {
+        bits_per_second(self.delivered, self.elapsed)
+    }
+
+    pub fn lost_packets(&self) -> u64 {
+        self.intervals.iter().map(|interval| interval.lost_packets).sum()
+    }
+
+    pub fn congestion_events(&self) -> u64 {
+        self.intervals.iter().map(|interval| interval.congestion_events).sum()
+    }
+
+    /// Whether `interval` stalled on retransmissions: packets were lost and it moved
+    /// less than a quarter of the median interval rate. Intervals shorter than half
+    /// the longest one are not judged.
+    pub fn is_stall(&self, interval: &Interval) -> bool {
+        let mut rates: Vec<f64> = self.intervals.iter().map(Interval::bits_per_second).collect();
+        rates.sort_by(f64::total_cmp);
+        let median = match rates.get(rates.len() / 2) {
+            Some(median) => *median,
+            None => return false,
+        };
+        let longest = self.intervals.iter().map(|other| other.end - other.start).max().unwrap_or_default();
+        interval.end - interval.start >= longest / 2
+            && interval.lost_packets > 0
+            && interval.bits_per_second() < median / 4.0
+    }
+
+    /// Number of intervals that stalled on retransmissions.
+    pub fn stalls(&self) -> usize {
+        self.intervals.iter().filter(|interval| self.is_stall(interval)).count()
+    }
+}
+
+impl fmt::Display for BulkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sent, {} delivered in {:.2} s over {}, goodput {}",
+               format_bytes(self.sent), format_bytes(self.delivered), self.elapsed.as_secs_f64(),
+               self.transport, format_bitrate(self.goodput()))?;
+        if self.delivered < self.sent {
+            write!(f, ", {:.1}% not delivered",
+                   (self.sent - self.delivered) as f64 * 100.0 / self.sent as f64)?;
+        }
+        write!(f, "\n{} lost packets, {} congestion events, {} stalled intervals",
+               self.lost_packets(), self.congestion_events(), self.stalls())
+    }
+}
+
+/// Runs a throughput test on `connection`, calling `on_interval` as each interval ends.
+/// Once `stop` completes, nothing more is sent and the test ends with the data in flight.
+pub(crate) async fn run(connection: &Connection,
+                        options: &BulkOptions,
+                        timeouts: Timeouts,
+                        mut on_interval: impl FnMut(&Interval),
+                        stop: impl Future<Output = ()>) -> Result<BulkReport, ClientError> {
+    let start = Instant::now();
+    let budget = Arc::new(Budget::new(options.limit, start));
+    let progress = Arc::new(AtomicU64::new(0));
+    let sent = Arc::new(AtomicU64::new(0));
+
+    let mut workers = JoinSet::new();
+    match options.transport {
+        BulkTransport::Datagram => {
+            // Echoes still in flight after the last datagram arrive within a few round trips.
+            let mut linger = (connection.rtt() * 4).max(Duration::from_millis(250));
+            if let Some(reply) = timeouts.reply {
+                linger = linger.min(reply);
+            }
+            workers.spawn(send_datagrams(connection.clone(), options.clone(), linger,
+                                         budget.clone(), progress.clone(), sent.clone()));
+        }
+        transport => {
+            for _ in 0..options.streams {
+                let worker = Stream {
+                    connection: connection.clone(),
+                    timeouts,
+                    budget: budget.clone(),
+                    progress: progress.clone(),
+                    sent: sent.clone(),
+                    chunk_size: options.chunk_size,
+                };
+                match (transport, options.echo) {
+                    (BulkTransport::Uni, _) => workers.spawn(worker.sink_uni()),
+                    (_, false) => workers.spawn(worker.sink_bi()),
+                    (_, true) => workers.spawn(worker.echo_bi()),
+                };
+            }
+        }
+    }
+
+    let mut sampler = Sampler::new(connection, start);
+    let mut ticker = time::interval_at(start + options.interval, options.interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut intervals = Vec::new();
+    let mut delivered = 0;
+    let mut finished = start;
+    let mut failure = None;
+    let mut stopping = false;
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            _ = &mut stop, if !stopping => {
+                stopping = true;
+                budget.stop();
+            }
+            _ = ticker.tick() => {
+                let interval = sampler.sample(progress.load(Ordering::Relaxed));
+                on_interval(&interval);
+                intervals.push(interval);
+            }
+            joined = workers.join_next() => match joined {
+                Some(Ok(Ok((bytes, at)))) => {
+                    delivered += bytes;
+                    finished = finished.max(at);
+                }
+                Some(Ok(Err(e))) => {
+                    failure.get_or_insert(e);
+                    workers.abort_all();
+                }
+                Some(Err(e)) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Some(Err(_)) => {}
+                None => break,
+            },
+        }
+    }
+    if let Some(e) = failure {
+        return Err(e);
+    }
+
+    let interval = sampler.sample(progress.load(Ordering::Relaxed));
+    if interval.bytes > 0 {
+        on_interval(&interval);
+        intervals.push(interval);
+    }
+    Ok(BulkReport {
+        transport: options.transport,
+        sent: sent.load(Ordering::Relaxed),
+        delivered,
+        elapsed: finished - start,
+        intervals,
+    })
+}
+
+/// Bytes or time left to send, shared by the senders of a test.
+struct Budget {
+    bytes: Option<AtomicU64>,
+    until: Option<Instant>,
+    stopped: AtomicBool,
+}
+
+impl Budget {
+    fn new(limit: Limit, start: Instant) -> Self {
+        let (bytes, until) = match limit {
+            Limit::Bytes(bytes) => (Some(AtomicU64::new(bytes)), None),
+            Limit::Duration(duration) => (None, Some(start + duration)),
+        };
+        Budget { bytes, until, stopped: AtomicBool::new(false) }
+    }
+
+    /// Spends what is left of the budget.
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether nothing is left to send, the time being up or the bytes claimed.
+    fn is_spent(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+            || self.until.is_some_and(|until| Instant::now() >= until)
+            || self.bytes.as_ref().is_some_and(|bytes| bytes.load(Ordering::Relaxed) == 0)
+    }
+
+    /// Completes once the time of a timed test is up, never for a byte budget.
+    async fn expired(&self) {
+        match self.until {
+            Some(until) => time::sleep_until(until).await,
+            None => future::pending().await,
+        }
+    }
+
+    /// Claims up to `want` bytes to send; 0 once the budget is spent.
+    fn take(&self, want: usize) -> usize {
+        if self.stopped.load(Ordering::Relaxed) || self.until.is_some_and(|until| Instant::now() >= until) {
+            return 0;
+        }
+        let bytes = match &self.bytes {
+            Some(bytes) => bytes,
+            None => return want,
+        };
+        let left = bytes.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+            Some(left.saturating_sub(want as u64))
+        });
+        left.unwrap_or_else(|left| left).min(want as u64) as usize
+    }
+}
+
+/// One stream of a stream throughput test.
+struct Stream {
+    connection: Connection,
+    timeouts: Timeouts,
+    budget: Arc<Budget>,
+    progress: Arc<AtomicU64>,
+    sent: Arc<AtomicU64>,
+    chunk_size: usize,
+}
+
+impl Stream {
+    /// Sends on a unidirectional stream; delivered once the server acknowledged it all.
+    async fn sink_uni(self) -> Result<(u64, Instant), ClientError> {
+        let mut send = self.within_open(open_uni(&self.connection)).await?;
+
+        let sent = self.send(&mut send, SINK, true).await?;
+        finish_bulk(&mut send).await?;
+        Ok((sent, Instant::now()))
+    }
+
+    /// Sends on a bidirectional stream; delivered as the byte count the server replies
+    /// within the reply timeout.
+    async fn sink_bi(self) -> Result<(u64, Instant), ClientError> {
+        let (mut send, mut recv) = self.within_open(open_bi(&self.connection)).await?;
+
+        let sent = self.send(&mut send, SINK, true).await?;
+        finish_bulk(&mut send).await?;
+
+        let reply = self.within_reply(read_reply(&mut recv)).await?;
+        match std::str::from_utf8(&reply).ok().and_then(|count| count.parse().ok()) {
+            Some(count) if count == sent => Ok((count, Instant::now())),
+            _ => Err(ClientError::ProtocolMismatch { expected: sent.to_string().into_bytes(), received: reply }),
+        }
+    }
+
+    /// Sends on a bidirectional stream while reading the echo; delivered as echoed. The
+    /// rest of the echo must arrive within the reply timeout once sending ended.
+    async fn echo_bi(self) -> Result<(u64, Instant), ClientError> {
+        let (mut send, mut recv) = self.within_open(open_bi(&self.connection)).await?;
+
+        let written = Notify::new();
+        let writer = async {
+            let sent = self.send(&mut send, ECHO, false).await?;
+            finish_bulk(&mut send).await?;
+            written.notify_one();
+            Ok(sent)
+        };
+        let reader = async {
+            let counting = count_to_end(&mut recv, &self.progress);
+            tokio::pin!(counting);
+            tokio::select! {
+                echoed = &mut counting => return echoed,
+                _ = written.notified() => {}
+            }
+            self.within_reply(counting).await
+        };
+        let (sent, echoed) = tokio::try_join!(writer, reader)?;
+        if echoed != sent {
+            return Err(ClientError::ProtocolMismatch {
+                expected: sent.to_string().into_bytes(),
+                received: echoed.to_string().into_bytes(),
+            });
+        }
+        Ok((echoed, Instant::now()))
+    }
+
+    async fn within_open<T, F>(&self, open: F) -> Result<T, ClientError>
+    where
+        F: std::future::Future<Output = Result<T, ClientError>>,
+    {
+        timeouts::within(Phase::StreamOpen, timeouts::deadline(self.timeouts.stream_open), None, open).await
+    }
+
+    async fn within_reply<T, F>(&self, reply: F) -> Result<T, ClientError>
+    where
+        F: std::future::Future<Output = Result<T, ClientError>>,
+    {
+        timeouts::within(Phase::Reply, timeouts::deadline(self.timeouts.reply), None, reply).await
+    }
+
+    /// Writes `header` then payload until the budget is spent, returning the payload
+    /// bytes written. Writes count as progress unless the echo is what is measured.
+    ///
+    /// The time of a timed test ends writing even while a write waits for flow control.
+    async fn send(&self, send: &mut SendStream, header: &[u8], counts: bool) -> Result<u64, ClientError> {
+        send.write_all(header).await.map_err(ClientError::BulkWrite)?;
+        let chunk = vec![0u8; self.chunk_size];
+        let expired = self.budget.expired();
+        tokio::pin!(expired);
+        let mut sent = 0;
+        loop {
+            let n = self.budget.take(chunk.len());
+            if n == 0 {
+                return Ok(sent);
+            }
+            let mut written = 0;
+            while written < n {
+                let w = tokio::select! {
+                    w = send.write(&chunk[written..n]) => w.map_err(ClientError::BulkWrite)?,
+                    _ = &mut expired => return Ok(sent),
+                };
+                written += w;
+                sent += w as u64;
+                self.sent.fetch_add(w as u64, Ordering::Relaxed);
+                if counts {
+                    self.progress.fetch_add(w as u64, Ordering::Relaxed);
+                }
+            }
+            // Writes that fit the send buffer complete at once; let the connection
+            // driver and the timers run between chunks.
+            task::yield_now().await;
+        }
+    }
+}
+
+async fn finish_bulk(send: &mut SendStream) -> Result<(), ClientError> {
+    match send.finish().await {
+        Ok(()) => Ok(()),
+        Err(e) => Err(ClientError::BulkWrite(e)),
+    }
+}
+
+/// Reads the short reply of a sunk bidirectional stream.
+async fn read_reply(recv: &mut RecvStream) -> Result<Vec<u8>, ClientError> {
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        match recv.read(&mut buf).await {
+            Ok(Some(n)) => reply.extend_from_slice(&buf[..n]),
+            Ok(None) => return Ok(reply),
+            Err(e) => return Err(ClientError::Read(e)),
+        }
+    }
+}
+
+/// Reads a stream to its end, adding what it read to `progress`.
+async fn count_to_end(recv: &mut RecvStream, progress: &AtomicU64) -> Result<u64, ClientError> {
+    let mut count = 0;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        match recv.read(&mut buf).await {
+            Ok(Some(n)) => {
+                count += n as u64;
+                progress.fetch_add(n as u64, Ordering::Relaxed);
+            }
+            Ok(None) => return Ok(count),
+            Err(e) => return Err(ClientError::Read(e)),
+        }
+    }
+}
+
+/// Sends datagrams paced at the requested bitrate and counts their echoes, waiting up
+/// to `linger` for the last ones. Finishes at the last echo.
+async fn send_datagrams(connection: Connection,
+                        options: BulkOptions,
+                        linger: Duration,
+                        budget: Arc<Budget>,
+                        progress: Arc<AtomicU64>,
+                        sent: Arc<AtomicU64>) -> Result<(u64, Instant), ClientError> {
+    let size = options.chunk_size.min(connection.max_datagram_size().unwrap_or(options.chunk_size));
+    let mut payload = DATAGRAM_PREFIX.to_vec();
+    payload.resize(size.max(DATAGRAM_PREFIX.len()), 0);
+
+    let start = Instant::now();
+    let mut pace = time::interval(Duration::from_millis(1));
+    pace.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let done = time::sleep(Duration::MAX);
+    tokio::pin!(done);
+    let mut sending = true;
+    let mut total_sent = 0u64;
+    let mut echoed = 0u64;
+    let mut last_echo = start;
+
+    loop {
+        tokio::select! {
+            _ = pace.tick(), if sending => {
+                if budget.is_spent() {
+                    sending = false;
+                    done.as_mut().reset(Instant::now() + linger);
+                }
+                let allowed = start.elapsed().as_secs_f64() * options.bitrate as f64 / 8.0;
+                while sending && (total_sent as f64) < allowed {
+                    let n = budget.take(payload.len());
+                    if n == 0 {
+                        sending = false;
+                        done.as_mut().reset(Instant::now() + linger);
+                        break;
+                    }
+                    let n = n.max(DATAGRAM_PREFIX.len());
+                    if let Err(e) = connection.send_datagram(&payload[..n]) {
+                        return Err(ClientError::DatagramSend(e));
+                    }
+                    total_sent += n as u64;
+                    sent.fetch_add(n as u64, Ordering::Relaxed);
+                }
+            }
+            datagram = connection.receive_datagram() => {
+                let datagram = match datagram {
+                    Ok(datagram) => datagram,
+                    Err(e) => return Err(ClientError::ConnectionLost(e)),
+                };
+                if datagram.payload().starts_with(DATAGRAM_PREFIX) {
+                    echoed += datagram.payload().len() as u64;
+                    last_echo = Instant::now();
+                    progress.fetch_add(datagram.payload().len() as u64, Ordering::Relaxed);
+                    if !sending && echoed >= total_sent {
+                        return Ok((echoed, last_echo));
+                    }
+                }
+            }
+            _ = &mut done => return Ok((echoed, last_echo)),
+        }
+    }
+}
+
+/// Turns the running byte count and the QUIC path statistics into intervals.
+struct Sampler<'a> {
+    connection: &'a Connection,
+    start: Instant,
+    last_at: Duration,
+    last_bytes: u64,
+    last_lost: u64,
+    last_congestion: u64,
+}
+
+impl<'a> Sampler<'a> {
+    fn new(connection: &'a Connection, start: Instant) -> Self {
+        let path = connection.quic_connection().stats().path;
+        Sampler {
+            connection,
+            start,
+            last_at: Duration::ZERO,
+            last_bytes: 0,
+            last_lost: path.lost_packets,
+            last_congestion: path.congestion_events,
+        }
+    }
+
+    fn sample(&mut self, bytes: u64) -> Interval {
+        let path = self.connection.quic_connection().stats().path;
+        let interval = Interval {
+            start: self.last_at,
+            end: self.start.elapsed(),
+            bytes: bytes - self.last_bytes,
+            lost_packets: path.lost_packets - self.last_lost,
+            congestion_events: path.congestion_events - self.last_congestion,
+        };
+        self.last_at = interval.end;
+        self.last_bytes = bytes;
+        self.last_lost = path.lost_packets;
+        self.last_congestion = path.congestion_events;
+        interval
+    }
+}
+
+fn bits_per_second(bytes: u64, elapsed: Duration) -> f64 {
+    match elapsed.as_secs_f64() {
+        secs if secs > 0.0 => bytes as f64 * 8.0 / secs,
+        _ => 0.0,
+    }
+}
+
+/// Formats a byte count with binary prefixes, e.g. `12.50 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", value, UNITS[unit])
+}
+
+/// Formats a rate with decimal prefixes, e.g. `95.3 Mbit/s`.
+pub fn format_bitrate(bits_per_second: f64) -> String {
+    const UNITS: [&str; 4] = ["bit/s", "kbit/s", "Mbit/s", "Gbit/s"];
+    let mut value = bits_per_second;
+    let mut unit = 0;
+    while value >= 1000.0 && unit + 1 < UNITS.len() {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(second: u64, bytes: u64, lost_packets: u64) -> Interval {
+        Interval {
+            start: Duration::from_secs(second),
+            end: Duration::from_secs(second + 1),
+            bytes,
+            lost_packets,
+            congestion_events: 0,
+        }
+    }
+
+    #[test]
+    fn stalls_need_losses_and_a_rate_drop() {
+        let report = BulkReport {
+            transport: BulkTransport::Bi,
+            sent: 3_100,
+            delivered: 3_100,
+            elapsed: Duration::from_secs(4),
+            intervals: vec![interval(0, 1_000, 0), interval(1, 100, 3), interval(2, 1_000, 2), interval(3, 1_000, 0)],
+        };
+        let tail = Interval { start: Duration::from_secs(4), end: Duration::from_millis(4_100), bytes: 1, lost_packets: 1, congestion_events: 0 };
+
+        assert_eq!(report.stalls(), 1);
+        assert!(report.is_stall(&report.intervals[1]));
+        assert!(!report.is_stall(&tail));
+        assert_eq!(report.lost_packets(), 5);
+        assert_eq!(report.goodput(), 6_200.0);
+    }
+
+    #[test]
+    fn byte_budget_is_shared_exactly() {
+        let budget = Budget::new(Limit::Bytes(10), Instant::now());
+
+        assert_eq!(budget.take(4), 4);
+        assert_eq!(budget.take(4), 4);
+        assert_eq!(budget.take(4), 2);
+        assert_eq!(budget.take(4), 0);
+    }
+
+    #[test]
+    fn budget_is_spent_once_its_bytes_are_claimed() {
+        let budget = Budget::new(Limit::Bytes(4), Instant::now());
+        assert!(!budget.is_spent());
+
+        budget.take(4);
+
+        assert!(budget.is_spent());
+    }
+
+    #[test]
+    fn stopped_budget_is_spent() {
+        let budget = Budget::new(Limit::Duration(Duration::from_secs(10)), Instant::now());
+        assert_eq!(budget.take(4), 4);
+
+        budget.stop();
+
+        assert_eq!(budget.take(4), 0);
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 * 1024 * 1024 / 2), "1.50 MiB");
+        assert_eq!(format_bitrate(95_300_000.0), "95.3 Mbit/s");
+    }
+}
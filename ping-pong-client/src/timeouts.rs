@@ -0,0 +1,117 @@
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time;
+use tokio::time::Instant;
+
+use crate::errors::ClientError;
+
+/// Phase of a probe bounded by its own deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Resolving the server and running the QUIC and TLS handshake up to the server
+    /// certificate.
+    Connect,
+    /// Finishing the handshake and getting the WebTransport session accepted.
+    Session,
+    /// Opening a stream on the session.
+    StreamOpen,
+    /// Sending a ping and waiting for its reply.
+    Reply,
+    /// Closing the session and waiting for the connection to finish closing.
+    Close,
+    /// The whole run, from the first connection attempt on.
+    Overall,
+}
+
+impl Phase {
+    /// Stable lower-case name of the phase.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Connect => "connect",
+            Phase::Session => "session",
+            Phase::StreamOpen => "stream open",
+            Phase::Reply => "reply",
+            Phase::Close => "close",
+            Phase::Overall => "overall",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Deadline of each [`Phase`]; `None` leaves a phase unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub session: Option<Duration>,
+    pub stream_open: Option<Duration>,
+    pub reply: Option<Duration>,
+    pub close: Option<Duration>,
+    pub overall: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Deadline configured for `phase`.
+    pub fn get(&self, phase: Phase) -> Option<Duration> {
+        match phase {
+            Phase::Connect => self.connect,
+            Phase::Session => self.session,
+            Phase::StreamOpen => self.stream_open,
+            Phase::Reply => self.reply,
+            Phase::Close => self.close,
+            Phase::Overall => self.overall,
+        }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect: Some(Duration::from_secs(5)),
+            session: Some(Duration::from_secs(5)),
+            stream_open: Some(Duration::from_secs(5)),
+            reply: Some(Duration::from_secs(5)),
+            close: Some(Duration::from_secs(1)),
+            overall: None,
+        }
+    }
+}
+
+/// Deadline of a phase of `limit` starting now.
+pub(crate) fn deadline(limit: Option<Duration>) -> Option<Instant> {
+    limit.map(|limit| Instant::now() + limit)
+}
+
+/// Time left until `deadline`, to bound a new client by the deadline of an earlier one.
+pub(crate) fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Runs `future` until the `phase` deadline, and never past the `overall` deadline.
+///
+/// Expiry is reported as [`ClientError::TimeOut`] naming whichever of the two
+/// deadlines was hit first.
+pub(crate) async fn within<T, F>(phase: Phase,
+                                 deadline: Option<Instant>,
+                                 overall: Option<Instant>,
+                                 future: F) -> Result<T, ClientError>
+where
+    F: Future<Output = Result<T, ClientError>>,
+{
+    let (deadline, expired) = match (deadline, overall) {
+        (Some(own), Some(overall)) if overall <= own => (overall, Phase::Overall),
+        (Some(own), _) => (own, phase),
+        (None, Some(overall)) => (overall, Phase::Overall),
+        (None, None) => return future.await,
+    };
+    match time::timeout_at(deadline, future).await {
+        Ok(result) => result,
+        Err(_) => Err(ClientError::TimeOut(expired)),
+    }
+}
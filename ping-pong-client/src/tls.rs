@@ -0,0 +1,565 @@
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+
+use pkcs8::EncryptedPrivateKeyInfo;
+use tokio::sync::Notify;
+use url::Url;
+use wtransport::tls::build_native_cert_store;
+use wtransport::tls::client::NoServerVerification;
+use wtransport::tls::client::ServerHashVerification;
+use wtransport::tls::rustls;
+use wtransport::tls::rustls::client::danger::HandshakeSignatureValid;
+use wtransport::tls::rustls::client::danger::ServerCertVerified;
+use wtransport::tls::rustls::client::danger::ServerCertVerifier;
+use wtransport::tls::rustls::client::ClientSessionMemoryCache;
+use wtransport::tls::rustls::client::ClientSessionStore;
+use wtransport::tls::rustls::client::Resumption;
+use wtransport::tls::rustls::client::ResolvesClientCert;
+use wtransport::tls::rustls::client::Tls12ClientSessionValue;
+use wtransport::tls::rustls::client::Tls13ClientSessionValue;
+use wtransport::tls::rustls::client::WebPkiServerVerifier;
+use wtransport::tls::rustls::crypto::ring;
+use wtransport::tls::rustls::pki_types::pem;
+use wtransport::tls::rustls::pki_types::pem::PemObject;
+use wtransport::tls::rustls::pki_types::CertificateDer;
+use wtransport::tls::rustls::pki_types::PrivateKeyDer;
+use wtransport::tls::rustls::pki_types::PrivatePkcs8KeyDer;
+use wtransport::tls::rustls::pki_types::ServerName;
+use wtransport::tls::rustls::pki_types::UnixTime;
+use wtransport::tls::rustls::sign::CertifiedKey;
+use wtransport::tls::rustls::DigitallySignedStruct;
+use wtransport::tls::rustls::InconsistentKeys;
+use wtransport::tls::rustls::NamedGroup;
+use wtransport::tls::rustls::SignatureScheme;
+use wtransport::tls::rustls::RootCertStore;
+use wtransport::tls::Sha256Digest;
+use wtransport::tls::WEBTRANSPORT_ALPN;
+
+use crate::errors::ClientError;
+
+/// Which server certificates the client accepts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Trust {
+    /// Certificates issued for the server name by one of the platform's root CAs.
+    #[default]
+    System,
+    /// Certificates issued for the server name by one of the CAs of a PEM bundle.
+    CaFile(PathBuf),
+    /// Certificates whose SHA-256 digest is listed, under the rules of the WebTransport
+    /// `serverCertificateHashes` option: valid for at most two weeks and ECDSA P-256.
+    Pinned(Vec<Sha256Digest>),
+    /// Any certificate; for local development only.
+    Insecure,
+}
+
+/// Certificate chain and private key presented to servers that authenticate clients.
+#[derive(Debug, Clone)]
+pub struct ClientIdentity(Arc<CertifiedKey>);
+
+impl ClientIdentity {
+    /// Pairs `certificates`, end-entity first, with the private key of the first one.
+    pub fn new(certificates: Vec<CertificateDer<'static>>,
+               key: PrivateKeyDer<'static>) -> Result<Self, rustls::Error> {
+        let key = ring::default_provider().key_provider.load_private_key(key)?;
+        let certified = CertifiedKey::new(certificates, key);
+        match certified.keys_match() {
+            Err(rustls::Error::InconsistentKeys(InconsistentKeys::Unknown)) | Ok(()) => {
+                Ok(ClientIdentity(Arc::new(certified)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Loads the certificate chain and the private key from PEM or DER files.
+    ///
+    /// The key may be PKCS#8, PKCS#1 or SEC1; an encrypted PKCS#8 key is decrypted
+    /// with `password`.
+    pub fn load(certificate_path: &Path,
+                key_path: &Path,
+                password: Option<&[u8]>) -> Result<Self, ClientError> {
+        let certificates = match load_certificates(certificate_path) {
+            Ok(certificates) => certificates,
+            Err(source) => return Err(ClientError::IdentityFile { path: certificate_path.to_path_buf(), source }),
+        };
+        let key = match load_key(key_path, password) {
+            Ok(key) => key,
+            Err(source) => return Err(ClientError::IdentityFile { path: key_path.to_path_buf(), source }),
+        };
+        match ClientIdentity::new(certificates, key) {
+            Ok(identity) => Ok(identity),
+            Err(e) => Err(ClientError::IdentityFile { path: key_path.to_path_buf(), source: invalid_data(e) }),
+        }
+    }
+}
+
+/// Identities are the same when they present the same certificate chain.
+impl PartialEq for ClientIdentity {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.cert == other.0.cert
+    }
+}
+
+impl ResolvesClientCert for ClientIdentity {
+    fn resolve(&self, _root_hint_subjects: &[&[u8]], _sigschemes: &[SignatureScheme]) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// TLS sessions of earlier connections, resumed by later connections sharing the
+/// cache instead of running a full handshake. Clones share the sessions.
+///
+/// rustls only resumes a session under the verifier and client certificate it was
+/// established with, so the cache keeps TLS configurations per trust, identity and
+/// key log: a connection only resumes sessions of connections with the same ones.
+/// Connections attempted at the same time each take a configuration of their own,
+/// and a later connection takes the one that last connected to its host.
+///
+/// Sessions live in memory only: rustls can neither export a session nor build one
+/// from saved bytes, so they cannot be written to disk. Resumed connections send no
+/// 0-RTT data either, as the wtransport client sends the session request only once
+/// the handshake is done; [`ConnectTimings`](crate::ConnectTimings) records whether
+/// the server would have taken it.
+#[derive(Clone, Default)]
+pub struct SessionCache(Arc<Mutex<Vec<SharedConfig>>>);
+
+impl SessionCache {
+    /// Sessions kept per configuration, enough for the few servers of a run.
+    const SESSIONS: usize = 64;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for SessionCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SessionCache")
+    }
+}
+
+/// TLS configurations shared by the connections of a [`SessionCache`] with the same
+/// trust, identity and key log, one per connection attempt in progress.
+struct SharedConfig {
+    trust: Trust,
+    identity: Option<ClientIdentity>,
+    key_log: Option<KeyLog>,
+    slots: Vec<Slot>,
+}
+
+/// TLS configuration with its own verifier and the sessions established under it.
+struct Slot {
+    config: rustls::ClientConfig,
+    watcher: Arc<Watcher>,
+}
+
+impl Slot {
+    fn new(trust: &Trust, identity: Option<&ClientIdentity>, key_log: Option<&KeyLog>) -> Result<Self, ClientError> {
+        let watcher = Arc::new(Watcher::default());
+        let verifier = ProgressVerifier::new(trust, watcher.clone())?;
+        let mut config = build_config(Arc::new(verifier), identity, key_log);
+        let store = TicketStore { inner: ClientSessionMemoryCache::new(SessionCache::SESSIONS), watcher: watcher.clone() };
+        config.resumption = Resumption::store(Arc::new(store));
+        Ok(Slot { config, watcher })
+    }
+}
+
+/// What the TLS configuration of a connection attempt saw of its handshake.
+#[derive(Debug, Default)]
+pub(crate) struct HandshakeProgress {
+    /// Notified once the server certificate has been verified.
+    pub(crate) certificate_verified: Notify,
+    offered: Mutex<Option<bool>>,
+}
+
+impl HandshakeProgress {
+    /// Whether a session of an earlier connection was offered to the server and, if
+    /// so, whether the server allowed 0-RTT data in it.
+    pub(crate) fn offered_session(&self) -> Option<bool> {
+        *self.offered.lock().expect("handshake progress poisoned")
+    }
+}
+
+/// Connection attempt served by the verifier and session store of a TLS
+/// configuration, or last served by them.
+#[derive(Debug, Default)]
+struct Watcher(Mutex<Watched>);
+
+#[derive(Debug, Default)]
+struct Watched {
+    host: Option<String>,
+    progress: Weak<HandshakeProgress>,
+}
+
+impl Watcher {
+    /// Serves the attempt to connect to `url`, for as long as `progress` lives.
+    fn watch(&self, url: &Url, progress: &Arc<HandshakeProgress>) {
+        let host = url.host_str().map(str::to_string);
+        let mut watched = self.0.lock().expect("verifier watcher poisoned");
+        *watched = Watched { host, progress: Arc::downgrade(progress) };
+    }
+
+    /// Whether no attempt is being served, and which host the last one connected to.
+    fn idle(&self) -> Option<Option<String>> {
+        let watched = self.0.lock().expect("verifier watcher poisoned");
+        match watched.progress.strong_count() {
+            0 => Some(watched.host.clone()),
+            _ => None,
+        }
+    }
+
+    fn progress(&self) -> Option<Arc<HandshakeProgress>> {
+        self.0.lock().expect("verifier watcher poisoned").progress.upgrade()
+    }
+}
+
+/// Session store of a [`Slot`], recording the session offered by the attempt it
+/// serves.
+#[derive(Debug)]
+struct TicketStore {
+    inner: ClientSessionMemoryCache,
+    watcher: Arc<Watcher>,
+}
+
+impl ClientSessionStore for TicketStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.inner.set_kx_hint(server_name, group)
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.inner.set_tls12_session(server_name, value)
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.inner.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.inner.remove_tls12_session(server_name)
+    }
+
+    fn insert_tls13_ticket(&self, server_name: ServerName<'static>, value: Tls13ClientSessionValue) {
+        self.inner.insert_tls13_ticket(server_name, value)
+    }
+
+    fn take_tls13_ticket(&self, server_name: &ServerName<'static>) -> Option<Tls13ClientSessionValue> {
+        let ticket = self.inner.take_tls13_ticket(server_name);
+        if let (Some(ticket), Some(progress)) = (&ticket, self.watcher.progress()) {
+            let zero_rtt = ticket.max_early_data_size() > 0;
+            *progress.offered.lock().expect("handshake progress poisoned") = Some(zero_rtt);
+        }
+        ticket
+    }
+}
+
+/// Log of the TLS secrets of the client's connections in the NSS key log format,
+/// which lets Wireshark decrypt captures of them. Clones append to the same file.
+#[derive(Clone)]
+pub struct KeyLog(Arc<KeyLogFile>);
+
+impl KeyLog {
+    /// Opens `path` for appending, creating it readable by its owner only.
+    pub fn open(path: &Path) -> Result<Self, ClientError> {
+        let mut options = OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        match options.open(path) {
+            Ok(file) => Ok(KeyLog(Arc::new(KeyLogFile { path: path.to_path_buf(), file: Mutex::new(file) }))),
+            Err(source) => Err(ClientError::KeyLogFile { path: path.to_path_buf(), source }),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0.path
+    }
+}
+
+/// Key logs are the same when they are clones of one another.
+impl PartialEq for KeyLog {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for KeyLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("KeyLog").field(&self.0.path).finish()
+    }
+}
+
+#[derive(Debug)]
+struct KeyLogFile {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl rustls::KeyLog for KeyLogFile {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = format!("{} {} {}\n", label, hex(client_random), hex(secret));
+        let mut file = self.file.lock().expect("key log poisoned");
+        // A secret that cannot be written only leaves its connection undecryptable.
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Builds the TLS configuration of a connection attempt to `url`.
+///
+/// `progress` is notified once the server certificate has been verified,
+/// which is how the connect phase is told apart from session establishment:
+/// wtransport runs the handshake and the session request as a single future. A
+/// resumed session skips the certificate, so `progress` is never notified.
+/// Without `sessions` the connection neither resumes nor leaves a session to resume.
+pub(crate) fn client_config(url: &Url,
+                            trust: &Trust,
+                            identity: Option<&ClientIdentity>,
+                            key_log: Option<&KeyLog>,
+                            sessions: Option<&SessionCache>,
+                            progress: &Arc<HandshakeProgress>) -> Result<rustls::ClientConfig, ClientError> {
+    let sessions = match sessions {
+        Some(sessions) => sessions,
+        None => {
+            let watcher = Arc::new(Watcher::default());
+            watcher.watch(url, progress);
+            let mut config = build_config(Arc::new(ProgressVerifier::new(trust, watcher)?), identity, key_log);
+            config.resumption = Resumption::disabled();
+            return Ok(config);
+        }
+    };
+
+    let mut configs = sessions.0.lock().expect("session cache poisoned");
+    let matching = configs.iter().position(|shared| {
+        shared.trust == *trust && shared.identity.as_ref() == identity && shared.key_log.as_ref() == key_log
+    });
+    let shared = match matching {
+        Some(i) => &mut configs[i],
+        None => {
+            configs.push(SharedConfig {
+                trust: trust.clone(),
+                identity: identity.cloned(),
+                key_log: key_log.cloned(),
+                slots: Vec::new(),
+            });
+            let last = configs.len() - 1;
+            &mut configs[last]
+        }
+    };
+
+    // Sessions are kept per slot, so an idle slot that last connected to the same host
+    // is the one holding its sessions.
+    let host = url.host_str();
+    let idle = shared.slots.iter().map(|slot| slot.watcher.idle()).collect::<Vec<_>>();
+    let same_host = idle.iter().position(|last| matches!(last, Some(last) if last.as_deref() == host));
+    let slot = match same_host.or_else(|| idle.iter().position(Option::is_some)) {
+        Some(i) => &shared.slots[i],
+        None => {
+            shared.slots.push(Slot::new(trust, identity, key_log)?);
+            &shared.slots[shared.slots.len() - 1]
+        }
+    };
+    slot.watcher.watch(url, progress);
+    Ok(slot.config.clone())
+}
+
+fn build_config(verifier: Arc<ProgressVerifier>,
+                identity: Option<&ClientIdentity>,
+                key_log: Option<&KeyLog>) -> rustls::ClientConfig {
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .expect("valid version")
+        .dangerous()
+        .with_custom_certificate_verifier(verifier);
+    let mut config = match identity {
+        Some(identity) => builder.with_client_cert_resolver(Arc::new(identity.clone())),
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = vec![WEBTRANSPORT_ALPN.to_vec()];
+    if let Some(key_log) = key_log {
+        config.key_log = key_log.0.clone();
+    }
+    config
+}
+
+/// Reads the password of an encrypted key from a file, without its trailing line break.
+pub fn read_password(path: &Path) -> io::Result<Vec<u8>> {
+    let mut password = fs::read(path)?;
+    while password.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+        password.pop();
+    }
+    Ok(password)
+}
+
+/// Parses a SHA-256 certificate hash written in hex, with or without colons.
+pub fn parse_digest(s: &str) -> Result<Sha256Digest, String> {
+    let hex = s.replace(':', "");
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err("expected a SHA-256 hash of 64 hex digits".to_string());
+    }
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .map_err(|_| format!("'{}' is not a hex SHA-256 hash", s))?;
+    }
+    Ok(Sha256Digest::new(digest))
+}
+
+/// Loads every certificate of the PEM bundle at `path` as a trust anchor.
+fn load_ca_file(path: &Path) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    let certificates = CertificateDer::pem_file_iter(path).map_err(pem_to_io)?;
+    for certificate in certificates {
+        let certificate = certificate.map_err(pem_to_io)?;
+        roots.add(certificate).map_err(io::Error::other)?;
+    }
+    Ok(roots)
+}
+
+/// Loads a PEM certificate chain, or a single DER certificate.
+fn load_certificates(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let bytes = fs::read(path)?;
+    if !is_pem(&bytes) {
+        return Ok(vec![CertificateDer::from(bytes)]);
+    }
+    let certificates = CertificateDer::pem_slice_iter(&bytes)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pem_to_io)?;
+    if certificates.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no certificate found"));
+    }
+    Ok(certificates)
+}
+
+/// Loads a PEM or DER private key, decrypting it with `password` if it is encrypted.
+fn load_key(path: &Path, password: Option<&[u8]>) -> io::Result<PrivateKeyDer<'static>> {
+    let bytes = fs::read(path)?;
+    let encrypted = if is_pem(&bytes) {
+        match pkcs8::Document::from_pem(&String::from_utf8_lossy(&bytes)) {
+            Ok(("ENCRYPTED PRIVATE KEY", document)) => document.as_bytes().to_vec(),
+            _ => return PrivateKeyDer::from_pem_slice(&bytes).map_err(pem_to_io),
+        }
+    } else if password.is_some() {
+        bytes
+    } else {
+        return PrivateKeyDer::try_from(bytes).map_err(invalid_data);
+    };
+
+    let password = match password {
+        Some(password) => password,
+        None => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "the key is encrypted; give its password with --key-password-file"))
+        }
+    };
+    let info = EncryptedPrivateKeyInfo::try_from(encrypted.as_slice()).map_err(invalid_data)?;
+    let document = info.decrypt(password).map_err(invalid_data)?;
+    Ok(PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(document.as_bytes().to_vec())))
+}
+
+fn is_pem(bytes: &[u8]) -> bool {
+    bytes.windows(11).any(|window| window == b"-----BEGIN ")
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Standard Web PKI verification against `roots`.
+fn web_pki(roots: RootCertStore) -> io::Result<Arc<dyn ServerCertVerifier>> {
+    let provider = Arc::new(ring::default_provider());
+    match WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider).build() {
+        Ok(verifier) => Ok(verifier),
+        Err(e) => Err(invalid_data(e)),
+    }
+}
+
+fn pem_to_io(error: pem::Error) -> io::Error {
+    match error {
+        pem::Error::Io(e) => e,
+        e => invalid_data(e),
+    }
+}
+
+/// Verifier delegating to `inner` that signals when the server certificate passed.
+///
+/// A verifier serves one connection attempt at a time, so it only wakes the attempt
+/// that presented the certificate. rustls only resumes a session under the verifier
+/// that established it, which is why a [`SessionCache`] hands its verifiers from one
+/// attempt to the next instead of making one per attempt.
+#[derive(Debug)]
+struct ProgressVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    watcher: Arc<Watcher>,
+}
+
+impl ProgressVerifier {
+    fn new(trust: &Trust, watcher: Arc<Watcher>) -> Result<Self, ClientError> {
+        let inner: Arc<dyn ServerCertVerifier> = match trust {
+            Trust::System => match web_pki(build_native_cert_store()) {
+                Ok(verifier) => verifier,
+                Err(source) => return Err(ClientError::TrustStore { path: None, source }),
+            },
+            Trust::CaFile(path) => match load_ca_file(path).and_then(web_pki) {
+                Ok(verifier) => verifier,
+                Err(source) => return Err(ClientError::TrustStore { path: Some(path.clone()), source }),
+            },
+            Trust::Pinned(hashes) => Arc::new(ServerHashVerification::new(hashes.iter().cloned())),
+            Trust::Insecure => Arc::new(NoServerVerification::new()),
+        };
+        Ok(ProgressVerifier { inner, watcher })
+    }
+}
+
+impl ServerCertVerifier for ProgressVerifier {
+    fn verify_server_cert(&self,
+                          end_entity: &CertificateDer<'_>,
+                          intermediates: &[CertificateDer<'_>],
+                          server_name: &ServerName<'_>,
+                          ocsp_response: &[u8],
+                          now: UnixTime) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        if let Some(progress) = self.watcher.progress() {
+            progress.certificate_verified.notify_one();
+        }
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(&self,
+                              message: &[u8],
+                              cert: &CertificateDer<'_>,
+                              dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(&self,
+                              message: &[u8],
+                              cert: &CertificateDer<'_>,
+                              dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
@@ -0,0 +1,486 @@
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use tokio::time::Instant;
+use url::Url;
+use wtransport::tls::rustls::pki_types::CertificateDer;
+use wtransport::tls::rustls::pki_types::PrivateKeyDer;
+use wtransport::tls::rustls::RootCertStore;
+use wtransport::tls::Sha256Digest;
+use wtransport::endpoint::endpoint_side;
+use wtransport::Endpoint;
+use wtransport::Identity;
+use wtransport::ServerConfig;
+use wtransport::VarInt;
+
+use ping_pong_client::fanout;
+use ping_pong_client::fleet::Fleet;
+use ping_pong_client::fleet::FleetTarget;
+use ping_pong_client::fleet::Health;
+use ping_pong_client::fleet::PingTransport;
+use ping_pong_client::reconnect;
+use ping_pong_client::reconnect::Backoff;
+use ping_pong_client::reuse;
+use ping_pong_client::throughput::BulkOptions;
+use ping_pong_client::throughput::BulkTransport;
+use ping_pong_client::throughput::Limit;
+use ping_pong_client::timeouts::Phase;
+use ping_pong_client::timeouts::Timeouts;
+use ping_pong_client::tls::ClientIdentity;
+use ping_pong_client::tls::KeyLog;
+use ping_pong_client::tls::SessionCache;
+use ping_pong_client::tls::Trust;
+use ping_pong_client::ClientError;
+use ping_pong_client::ClientOptions;
+use ping_pong_client::Closed;
+use ping_pong_client::PingClient;
+use ping_pong_server_rs::Server;
+
+/// Starts a server and returns its URL with options pinning its certificate.
+async fn start_server() -> (Url, ClientOptions) {
+    let identity = Identity::self_signed(["localhost"]).unwrap();
+    let hash = identity.certificate_chain().as_slice()[0].hash();
+    let server = Server::bind(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0), identity).unwrap();
+    let port = server.local_addr().unwrap().port();
+    tokio::spawn(server.serve());
+
+    let url = format!("https://[::1]:{}/", port).parse().unwrap();
+    (url, ClientOptions { trust: Trust::Pinned(vec![hash]), ..ClientOptions::default() })
+}
+
+/// Binds a bare WebTransport endpoint for a test to script the server side, and
+/// returns it with its URL and options pinning its certificate.
+fn bare_server() -> (Endpoint<endpoint_side::Server>, Url, ClientOptions) {
+    let identity = Identity::self_signed(["localhost"]).unwrap();
+    let hash = identity.certificate_chain().as_slice()[0].hash();
+    let config = ServerConfig::builder()
+        .with_bind_address(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0))
+        .with_identity(identity)
+        .build();
+    let server = Endpoint::server(config).unwrap();
+    let url = format!("https://[::1]:{}/", server.local_addr().unwrap().port()).parse().unwrap();
+    (server, url, ClientOptions { trust: Trust::Pinned(vec![hash]), ..ClientOptions::default() })
+}
+
+#[tokio::test]
+async fn pings_over_streams_and_datagrams() {
+    let (url, options) = start_server().await;
+    let client = PingClient::connect(&url, &options).await.unwrap();
+
+    let pong = client.ping().await.unwrap();
+    assert_eq!(pong.stream_id % 4, 0, "client-initiated bidirectional stream");
+    client.ping_datagram().await.unwrap();
+    client.close().await;
+}
+
+#[tokio::test]
+async fn pings_over_unidirectional_streams() {
+    let (url, options) = start_server().await;
+    let client = PingClient::connect(&url, &options).await.unwrap();
+
+    for _ in 0..2 {
+        let pong = client.ping_uni().await.unwrap();
+        assert_eq!(pong.stream_id % 4, 2, "client-initiated unidirectional stream");
+    }
+    client.close().await;
+}
+
+#[tokio::test]
+async fn uni_ping_skips_late_pongs_and_rejects_those_of_other_pings() {
+    let (server, url, options) = bare_server();
+    tokio::spawn(async move {
+        let request = server.accept().await.await.unwrap();
+        let connection = request.accept().await.unwrap();
+        for replies in [&[&b"pong:0"[..], b"pong:1"][..], &[b"pong:9"]] {
+            let mut recv = connection.accept_uni().await.unwrap();
+            recv.read_to_end(&mut Vec::new()).await.unwrap();
+            for reply in replies {
+                let mut send = connection.open_uni().await.unwrap().await.unwrap();
+                send.write_all(reply).await.unwrap();
+                send.finish().await.unwrap();
+            }
+        }
+        connection.closed().await;
+    });
+    let client = PingClient::connect(&url, &options).await.unwrap();
+
+    client.ping_uni().await.unwrap();
+    let mismatch = client.ping_uni().await.unwrap_err();
+
+    assert!(matches!(mismatch, ClientError::ProtocolMismatch { expected, received }
+                     if expected == b"pong:2" && received == b"pong:9"));
+    client.close().await;
+}
+
+#[tokio::test]
+async fn ping_rejects_data_after_the_reply_but_not_a_stream_left_open() {
+    let (server, url, options) = bare_server();
+    tokio::spawn(async move {
+        let request = server.accept().await.await.unwrap();
+        let connection = request.accept().await.unwrap();
+        let (mut send, mut recv) = connection.accept_bi().await.unwrap();
+        recv.read_to_end(&mut Vec::new()).await.unwrap();
+        send.write_all(b"pong").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        send.write_all(b"!").await.unwrap();
+        send.finish().await.unwrap();
+
+        let (mut send, mut recv) = connection.accept_bi().await.unwrap();
+        recv.read_to_end(&mut Vec::new()).await.unwrap();
+        send.write_all(b"pong").await.unwrap();
+        connection.closed().await;
+    });
+    let client = PingClient::connect(&url, &options).await.unwrap();
+
+    let trailing = client.ping().await.unwrap_err();
+    assert!(matches!(trailing, ClientError::ProtocolMismatch { received, .. } if received == b"pong!"));
+    client.ping().await.unwrap();
+    client.close().await;
+}
+
+#[tokio::test]
+async fn stream_throughput_times_out_without_a_byte_count_or_echo() {
+    let (server, url, options) = bare_server();
+    tokio::spawn(async move {
+        let request = server.accept().await.await.unwrap();
+        let connection = request.accept().await.unwrap();
+        loop {
+            let (send, mut recv) = connection.accept_bi().await.unwrap();
+            tokio::spawn(async move {
+                recv.read_to_end(&mut Vec::new()).await.unwrap();
+                std::future::pending::<()>().await;
+                drop(send);
+            });
+        }
+    });
+    let timeouts = Timeouts { reply: Some(Duration::from_millis(200)), ..Timeouts::default() };
+    let client = PingClient::connect(&url, &ClientOptions { timeouts, ..options }).await.unwrap();
+
+    for echo in [false, true] {
+        let options = BulkOptions { limit: Limit::Bytes(10_000), echo, ..BulkOptions::default() };
+        let result = client.throughput(&options, |_| {}).await;
+
+        assert!(matches!(result, Err(ClientError::TimeOut(Phase::Reply))), "echo: {}", echo);
+    }
+    client.close().await;
+}
+
+#[tokio::test]
+async fn throughput_delivers_the_byte_budget() {
+    let (url, options) = start_server().await;
+    let client = PingClient::connect(&url, &options).await.unwrap();
+
+    for (transport, echo) in [(BulkTransport::Bi, false), (BulkTransport::Bi, true), (BulkTransport::Uni, false)] {
+        let options = BulkOptions { transport, streams: 2, limit: Limit::Bytes(100_000), echo, ..BulkOptions::default() };
+        let report = client.throughput(&options, |_| {}).await.unwrap();
+
+        assert_eq!(report.sent, 100_000);
+        assert_eq!(report.delivered, 100_000);
+    }
+    client.close().await;
+}
+
+#[tokio::test]
+async fn timed_datagram_throughput_ends_on_time_at_a_low_bitrate() {
+    let (url, options) = start_server().await;
+    let client = PingClient::connect(&url, &options).await.unwrap();
+    let options = BulkOptions {
+        transport: BulkTransport::Datagram,
+        limit: Limit::Duration(Duration::from_millis(300)),
+        bitrate: 8,
+        ..BulkOptions::default()
+    };
+
+    let test = client.throughput(&options, |_| {});
+    let report = tokio::time::timeout(Duration::from_secs(5), test).await.unwrap().unwrap();
+
+    assert!(report.sent > 0);
+    client.close().await;
+}
+
+#[tokio::test]
+async fn fan_out_pings_every_stream_of_every_connection() {
+    let (url, options) = start_server().await;
+    let clients = vec![PingClient::connect(&url, &options).await.unwrap(),
+                       PingClient::connect(&url, &options).await.unwrap()];
+
+    let round = fanout::round(&clients, 4).await;
+
+    assert_eq!(round.replies.len(), 8);
+    assert!(round.replies.iter().all(|reply| reply.result.is_ok()));
+    assert_eq!((round.replies[7].connection, round.replies[7].stream), (1, 3));
+}
+
+#[tokio::test]
+async fn fleet_reports_each_target_and_a_verdict() {
+    let (url, options) = start_server().await;
+    let target = |name: &str, expected_reply: &[u8], transport| FleetTarget {
+        name: name.to_string(),
+        url: url.clone(),
+        options: ClientOptions { expected_reply: Some(expected_reply.to_vec()), ..options.clone() },
+        transport,
+        count: 2,
+        interval: Duration::from_millis(10),
+    };
+    let fleet = Fleet {
+        targets: vec![target("streams", b"pong", PingTransport::Bi),
+                      target("datagrams", b"pong", PingTransport::Datagram),
+                      target("wrong reply", b"quack", PingTransport::Bi)],
+    };
+
+    let report = fleet.probe().await;
+
+    let health: Vec<Health> = report.targets.iter().map(|target| target.health()).collect();
+    assert_eq!(health, [Health::Healthy, Health::Healthy, Health::Down]);
+    assert!(matches!(report.targets[2].error, Some(ClientError::ProtocolMismatch { .. })));
+    assert_eq!(report.verdict(), Health::Degraded);
+}
+
+#[tokio::test]
+async fn stopped_fleet_reports_the_pings_sent_until_then() {
+    let (url, options) = start_server().await;
+    let target = FleetTarget {
+        name: "streams".to_string(),
+        url,
+        options,
+        transport: PingTransport::Bi,
+        count: 1000,
+        interval: Duration::from_millis(20),
+    };
+    let fleet = Fleet { targets: vec![target.clone(), FleetTarget { transport: PingTransport::Datagram, ..target }] };
+
+    let report = fleet.probe_until(tokio::time::sleep(Duration::from_millis(300))).await;
+
+    for target in &report.targets {
+        assert!(target.stats.transmitted() > 0 && target.stats.transmitted() < 1000);
+        assert_eq!(target.health(), Health::Healthy);
+    }
+}
+
+#[tokio::test]
+async fn fresh_ping_times_each_step_of_connecting() {
+    let (url, options) = start_server().await;
+
+    let fresh = reuse::fresh_ping(&url, &options, None).await.unwrap();
+
+    assert!(!fresh.timings.handshake.is_zero());
+    assert!(!fresh.rtt.is_zero());
+    assert_eq!(fresh.total(), fresh.timings.resolve + fresh.timings.handshake + fresh.timings.session + fresh.rtt);
+}
+
+#[tokio::test]
+async fn connections_sharing_a_session_cache_resume() {
+    let (url, options) = start_server().await;
+    let options = ClientOptions { sessions: Some(SessionCache::new()), ..options };
+
+    let full = reuse::fresh_ping(&url, &options, None).await.unwrap();
+    let resumed = reuse::fresh_ping(&url, &options, None).await.unwrap();
+
+    assert!(!full.timings.resumed);
+    assert!(!full.timings.resumption_offered);
+    assert!(resumed.timings.resumed);
+    assert!(resumed.timings.resumption_offered);
+    assert!(!reuse::fresh_ping(&url, &ClientOptions { sessions: None, ..options }, None).await.unwrap().timings.resumed);
+}
+
+#[tokio::test]
+async fn concurrent_connections_only_wait_for_their_own_certificate() {
+    let (url, options) = start_server().await;
+    let options = ClientOptions { sessions: Some(SessionCache::new()), ..options };
+
+    reuse::fresh_ping(&url, &options, None).await.unwrap();
+    let (first, second) = tokio::join!(reuse::fresh_ping(&url, &options, None),
+                                       reuse::fresh_ping(&url, &options, None));
+    let (first, second) = (first.unwrap().timings, second.unwrap().timings);
+
+    assert_ne!(first.resumed, second.resumed);
+    let full = if first.resumed { second } else { first };
+    assert!(!full.session.is_zero());
+}
+
+#[tokio::test]
+async fn sessions_are_only_resumed_under_the_same_trust() {
+    let (url, options) = start_server().await;
+    let options = ClientOptions { sessions: Some(SessionCache::new()), ..options };
+    let untrusting = ClientOptions { trust: Trust::Pinned(vec![Sha256Digest::new([0; 32])]), ..options.clone() };
+
+    reuse::fresh_ping(&url, &options, None).await.unwrap();
+    let result = reuse::fresh_ping(&url, &untrusting, None).await;
+
+    assert!(matches!(result, Err(ClientError::TlsHandshake(_))));
+    assert!(reuse::fresh_ping(&url, &options, None).await.unwrap().timings.resumed);
+}
+
+#[tokio::test]
+async fn key_log_records_the_secrets_of_the_connection() {
+    let (url, options) = start_server().await;
+    let path = std::env::temp_dir().join(format!("ping-pong-client-keylog-{}", std::process::id()));
+    let options = ClientOptions { key_log: Some(KeyLog::open(&path).unwrap()), ..options };
+
+    let client = PingClient::connect(&url, &options).await.unwrap();
+    client.close().await;
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let labels: Vec<&str> = log.lines().filter_map(|line| line.split(' ').next()).collect();
+    assert!(labels.contains(&"CLIENT_HANDSHAKE_TRAFFIC_SECRET"));
+    assert!(labels.contains(&"SERVER_TRAFFIC_SECRET_0"));
+}
+
+#[tokio::test]
+async fn qlog_traces_the_connection_from_start_to_close() {
+    let (url, options) = start_server().await;
+    let dir = std::env::temp_dir().join(format!("ping-pong-client-qlog-{}", std::process::id()));
+    let options = ClientOptions { qlog_dir: Some(dir.clone()), ..options };
+
+    let client = PingClient::connect(&url, &options).await.unwrap();
+    client.ping().await.unwrap();
+    let path = client.qlog_path().unwrap().to_path_buf();
+    client.close().await;
+
+    let trace = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let names: Vec<String> = trace.split('\x1e')
+        .filter_map(|record| serde_json::from_str::<serde_json::Value>(record).ok())
+        .filter_map(|record| record["name"].as_str().map(String::from))
+        .collect();
+    assert_eq!(names.first().map(String::as_str), Some("connectivity:connection_started"));
+    assert!(names.iter().any(|name| name == "webtransport:session_established"));
+    assert_eq!(names.iter().filter(|name| *name == "transport:stream_data_moved").count(), 2);
+    assert!(names.iter().any(|name| name == "transport:packet_sent"));
+    assert!(names.iter().any(|name| name == "transport:packet_received"));
+    assert!(names.iter().any(|name| name == "connectivity:connection_closed"));
+}
+
+#[tokio::test]
+async fn reconnects_until_the_server_answers() {
+    let (url, options) = start_server().await;
+    let backoff = Backoff { initial: Duration::from_millis(10), ..Backoff::default() };
+    let lost = ClientError::TimeOut(Phase::Reply);
+
+    let (result, outage) = reconnect::reconnect(&url, &options, &backoff, None, Instant::now(), lost).await;
+
+    let client = result.unwrap();
+    client.ping().await.unwrap();
+    assert_eq!(outage.attempts, 1);
+    assert!(matches!(outage.cause, ClientError::TimeOut(Phase::Reply)));
+    client.close().await;
+}
+
+#[tokio::test]
+async fn reconnecting_gives_up_after_the_attempt_cap() {
+    let silent = UdpSocket::bind(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0)).unwrap();
+    let url = format!("https://[::1]:{}/", silent.local_addr().unwrap().port()).parse().unwrap();
+    let options = ClientOptions {
+        timeouts: Timeouts { connect: Some(Duration::from_millis(50)), ..Timeouts::default() },
+        ..ClientOptions::default()
+    };
+    let backoff = Backoff { initial: Duration::from_millis(10), attempts: Some(3), ..Backoff::default() };
+    let lost = ClientError::TimeOut(Phase::Reply);
+
+    let (result, outage) = reconnect::reconnect(&url, &options, &backoff, None, Instant::now(), lost).await;
+
+    assert!(matches!(result, Err(ClientError::TimeOut(Phase::Connect))));
+    assert_eq!(outage.attempts, 3);
+}
+
+#[tokio::test]
+async fn close_reports_the_code_and_reason_of_the_client() {
+    let (url, options) = start_server().await;
+    let client = PingClient::connect(&url, &options).await.unwrap();
+
+    let closed = client.close_with(7, "done").await;
+
+    assert_eq!(closed, Closed::ByClient { code: 7, reason: "done".to_string(), drained: true });
+}
+
+#[tokio::test]
+async fn close_reports_the_server_closing_first() {
+    let (server, url, options) = bare_server();
+    let (close, closing) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let request = server.accept().await.await.unwrap();
+        let connection = request.accept().await.unwrap();
+        closing.await.unwrap();
+        connection.close(VarInt::from_u32(42), b"going away");
+        connection.closed().await;
+    });
+    let client = PingClient::connect(&url, &options).await.unwrap();
+    close.send(()).unwrap();
+
+    let lost = client.ping().await.unwrap_err();
+    assert_eq!(lost.to_string(), "connection lost: connection closed by peer: going away (code 42)");
+    let closed = client.close().await;
+
+    assert_eq!(closed, Closed::ByServer { code: 42, reason: "going away".to_string() });
+}
+
+#[tokio::test]
+async fn unknown_path_is_rejected() {
+    let (url, options) = start_server().await;
+    let result = PingClient::connect(&url.join("/unknown").unwrap(), &options).await;
+
+    assert!(matches!(result, Err(ClientError::SessionRejected)));
+}
+
+#[tokio::test]
+async fn server_certificate_is_verified_by_default() {
+    let (url, _) = start_server().await;
+    let result = PingClient::connect(&url, &ClientOptions::default()).await;
+
+    assert!(matches!(result, Err(ClientError::TlsHandshake(_))));
+}
+
+#[tokio::test]
+async fn unpinned_certificate_fails_tls() {
+    let (url, _) = start_server().await;
+    let options = ClientOptions { trust: Trust::Pinned(vec![Sha256Digest::new([0; 32])]), ..ClientOptions::default() };
+    let result = PingClient::connect(&url, &options).await;
+
+    assert!(matches!(result, Err(ClientError::TlsHandshake(_))));
+}
+
+#[tokio::test]
+async fn client_certificate_is_required_by_authenticating_server() {
+    let client = Identity::self_signed(["client"]).unwrap();
+    let client_certificate = CertificateDer::from(client.certificate_chain().as_slice()[0].der().to_vec());
+    let mut client_roots = RootCertStore::empty();
+    client_roots.add(client_certificate.clone()).unwrap();
+
+    let identity = Identity::self_signed(["localhost"]).unwrap();
+    let hash = identity.certificate_chain().as_slice()[0].hash();
+    let server = Server::bind_with_client_auth(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0),
+                                               identity,
+                                               client_roots).unwrap();
+    let url: Url = format!("https://[::1]:{}/", server.local_addr().unwrap().port()).parse().unwrap();
+    tokio::spawn(server.serve());
+
+    let anonymous = ClientOptions { trust: Trust::Pinned(vec![hash]), ..ClientOptions::default() };
+    let result = PingClient::connect(&url, &anonymous).await;
+    assert!(matches!(result, Err(ClientError::ClientCertificateRejected(_))));
+
+    let key = PrivateKeyDer::try_from(client.private_key().secret_der().to_vec()).unwrap();
+    let authenticated = ClientOptions {
+        identity: Some(ClientIdentity::new(vec![client_certificate], key).unwrap()),
+        ..anonymous
+    };
+    let client = PingClient::connect(&url, &authenticated).await.unwrap();
+    client.ping().await.unwrap();
+    client.close().await;
+}
+
+#[tokio::test]
+async fn silent_server_times_out_in_connect_phase() {
+    let silent = UdpSocket::bind(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0)).unwrap();
+    let url = format!("https://[::1]:{}/", silent.local_addr().unwrap().port()).parse().unwrap();
+    let options = ClientOptions {
+        timeouts: Timeouts { connect: Some(Duration::from_millis(200)), ..Timeouts::default() },
+        ..ClientOptions::default()
+    };
+    let result = PingClient::connect(&url, &options).await;
+
+    assert!(matches!(result, Err(ClientError::TimeOut(Phase::Connect))));
+}
@@ -0,0 +1,381 @@
+//! Native ping-pong server.
+//!
+//! Implements the same contract as the Python server in `ping-pong-server/main.py`:
+//! `ping` is answered with `pong` on bidirectional streams, WebTransport datagrams are
+//! echoed back, raw QUIC `quack` datagrams on `siduck` connections get a `quack-ack`,
+//! and `/` answers plain HTTP requests with "Server is running".
+//!
+//! Beyond it, a unidirectional stream carrying `ping` is answered on a new
+//! unidirectional stream from the server carrying `pong`; whatever followed `ping`,
+//! such as the `:7` of `ping:7`, follows `pong` too so the client can tell which
+//! ping a reply answers.
+//!
+//! Where it differs from the Python server:
+//!
+//! - `/` is served over HTTP/1.1 on TCP rather than HTTP/3: wtransport only takes
+//!   the `CONNECT` requests of WebTransport sessions, and has no HTTP/3 handler for
+//!   plain requests.
+//! - The Python server answers every chunk of a bidirectional stream that is exactly
+//!   `ping`, and leaves its side of the stream open. QUIC does not keep the chunks
+//!   of a stream apart, so this server answers once the data of the stream so far is
+//!   `ping`, without waiting for its end, and then finishes its side.
+//! - Only the WebTransport and `siduck` ALPNs are offered; the Python server also
+//!   offers HTTP/0.9 over QUIC (`hq-interop`).
+//!
+//! For throughput tests, a stream starting with [`SINK`] is read to its end and, on a
+//! bidirectional stream, answered with the decimal count of bytes that followed the
+//! header; a bidirectional stream starting with [`ECHO`] gets everything after the
+//! header written back.
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tiny_http::Header;
+use tiny_http::Response;
+use tracing::warn;
+use wtransport::endpoint::IncomingSessionFuture;
+use wtransport::quinn;
+use wtransport::quinn::crypto::rustls::HandshakeData;
+use wtransport::tls::rustls;
+use wtransport::tls::rustls::crypto::ring;
+use wtransport::tls::rustls::pki_types::CertificateDer;
+use wtransport::tls::rustls::pki_types::PrivateKeyDer;
+use wtransport::tls::rustls::server::WebPkiClientVerifier;
+use wtransport::tls::rustls::RootCertStore;
+use wtransport::tls::server::build_default_tls_config;
+use wtransport::tls::WEBTRANSPORT_ALPN;
+use wtransport::Connection;
+use wtransport::Identity;
+use wtransport::RecvStream;
+use wtransport::SendStream;
+use wtransport::ServerConfig;
+
+pub const PING: &[u8] = b"ping";
+pub const PONG: &[u8] = b"pong";
+pub const QUACK: &[u8] = b"quack";
+/// Header of a stream whose data is discarded.
+pub const SINK: &[u8] = b"sink\n";
+/// Header of a bidirectional stream whose data is echoed back.
+pub const ECHO: &[u8] = b"echo\n";
+pub const QUACK_ACK: &[u8] = b"quack-ack";
+
+/// ALPN of the QUIC datagram test protocol answered with `quack-ack`.
+pub const SIDUCK_ALPN: &[u8] = b"siduck";
+
+/// Body of the plain HTTP `/` response.
+pub const HOMEPAGE: &str = "Server is running";
+
+/// Largest stream payload the server reads before answering.
+const MAX_REQUEST_LEN: usize = 64;
+
+/// A QUIC endpoint serving WebTransport sessions and `siduck` connections.
+pub struct Server {
+    endpoint: quinn::Endpoint,
+}
+
+impl Server {
+    /// Binds the QUIC endpoint to `addr`, presenting `identity` to clients.
+    pub fn bind(addr: SocketAddr, identity: Identity) -> io::Result<Self> {
+        Server::with_tls(addr, build_default_tls_config(identity))
+    }
+
+    /// Binds like [`Server::bind`], and only accepts clients presenting a certificate
+    /// issued by one of `client_roots`.
+    pub fn bind_with_client_auth(addr: SocketAddr,
+                                 identity: Identity,
+                                 client_roots: RootCertStore) -> io::Result<Self> {
+        let provider = Arc::new(ring::default_provider());
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(client_roots), provider.clone())
+            .build()
+            .map_err(io::Error::other)?;
+
+        let certificates = identity
+            .certificate_chain()
+            .as_slice()
+            .iter()
+            .map(|certificate| CertificateDer::from(certificate.der().to_vec()))
+            .collect();
+        let key = PrivateKeyDer::try_from(identity.private_key().secret_der().to_vec())
+            .map_err(io::Error::other)?;
+
+        let tls_config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .expect("valid version")
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(certificates, key)
+            .map_err(io::Error::other)?;
+        Server::with_tls(addr, tls_config)
+    }
+
+    fn with_tls(addr: SocketAddr, mut tls_config: rustls::ServerConfig) -> io::Result<Self> {
+        tls_config.alpn_protocols = vec![WEBTRANSPORT_ALPN.to_vec(), SIDUCK_ALPN.to_vec()];
+
+        let config = ServerConfig::builder()
+            .with_bind_address(addr)
+            .with_custom_tls(tls_config)
+            .build();
+
+        let endpoint = quinn::Endpoint::server(config.quic_config().clone(), addr)?;
+        Ok(Server { endpoint })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    /// Accepts connections until the endpoint is closed.
+    pub async fn serve(self) {
+        while let Some(incoming) = self.endpoint.accept().await {
+            tokio::spawn(async move {
+                let remote = incoming.remote_address();
+                if let Err(e) = handle_incoming(incoming).await {
+                    warn!(%remote, error = %e, "connection failed");
+                }
+            });
+        }
+    }
+}
+
+async fn handle_incoming(incoming: quinn::Incoming) -> Result<(), quinn::ConnectionError> {
+    let mut connecting = incoming.accept()?;
+    let alpn = connecting
+        .handshake_data()
+        .await?
+        .downcast::<HandshakeData>()
+        .ok()
+        .and_then(|data| data.protocol);
+
+    if alpn.as_deref() == Some(SIDUCK_ALPN) {
+        serve_siduck(connecting.await?).await;
+        return Ok(());
+    }
+
+    let request = match IncomingSessionFuture::with_quic_connecting(connecting).await {
+        Ok(request) => request,
+        Err(e) => {
+            warn!(error = %e, "session request failed");
+            return Ok(());
+        }
+    };
+    if request.path() != "/" {
+        request.not_found().await;
+        return Ok(());
+    }
+    match request.accept().await {
+        Ok(connection) => serve_session(connection).await,
+        Err(e) => warn!(error = %e, "session accept failed"),
+    }
+    Ok(())
+}
+
+/// Answers `quack` datagrams with `quack-ack` until the connection closes.
+async fn serve_siduck(connection: quinn::Connection) {
+    while let Ok(datagram) = connection.read_datagram().await {
+        if datagram.as_ref() == QUACK && connection.send_datagram(QUACK_ACK.into()).is_err() {
+            break;
+        }
+    }
+}
+
+/// Answers streams and echoes datagrams until the session closes.
+async fn serve_session(connection: Connection) {
+    loop {
+        tokio::select! {
+            stream = connection.accept_bi() => match stream {
+                Ok((send, recv)) => {
+                    tokio::spawn(answer_bi(send, recv));
+                }
+                Err(_) => break,
+            },
+            stream = connection.accept_uni() => match stream {
+                Ok(recv) => {
+                    tokio::spawn(answer_uni(connection.clone(), recv));
+                }
+                Err(_) => break,
+            },
+            datagram = connection.receive_datagram() => match datagram {
+                Ok(datagram) => {
+                    let _ = connection.send_datagram(datagram.payload());
+                }
+                Err(_) => break,
+            },
+        }
+    }
+}
+
+/// Dispatches a bidirectional stream on its header: sink, echo, or otherwise a ping,
+/// answered with `pong` while any other request gets an empty stream.
+async fn answer_bi(mut send: SendStream, mut recv: RecvStream) {
+    let (head, ended) = match read_head(&mut recv).await {
+        Some(head) => head,
+        None => return,
+    };
+
+    if let Some(rest) = head.strip_prefix(SINK) {
+        if let Some(count) = drain(&mut recv).await {
+            let count = rest.len() as u64 + count;
+            if send.write_all(count.to_string().as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    } else if let Some(rest) = head.strip_prefix(ECHO) {
+        if echo(&mut send, &mut recv, rest).await.is_none() {
+            return;
+        }
+    } else {
+        let request = match ended {
+            true => head,
+            false => match read_ping(&mut recv, head).await {
+                Some(request) => request,
+                None => return,
+            },
+        };
+        if request == PING && send.write_all(PONG).await.is_err() {
+            return;
+        }
+    }
+    let _ = send.finish().await;
+}
+
+/// Discards a unidirectional [`SINK`] stream and answers a unidirectional ping on a
+/// stream of its own; other unidirectional streams are ignored.
+async fn answer_uni(connection: Connection, mut recv: RecvStream) {
+    let (head, ended) = match read_head(&mut recv).await {
+        Some(head) => head,
+        None => return,
+    };
+    if head.starts_with(SINK) {
+        drain(&mut recv).await;
+        return;
+    }
+
+    let request = match ended {
+        true => head,
+        false => match read_request(&mut recv, head).await {
+            Some(request) => request,
+            None => return,
+        },
+    };
+    let token = match request.strip_prefix(PING) {
+        Some(token) => token,
+        None => return,
+    };
+    let mut send = match connection.open_uni().await {
+        Ok(opening) => match opening.await {
+            Ok(send) => send,
+            Err(_) => return,
+        },
+        Err(_) => return,
+    };
+    if send.write_all(&[PONG, token].concat()).await.is_ok() {
+        let _ = send.finish().await;
+    }
+}
+
+/// Reads until the stream holds enough bytes to tell its header, or can no longer
+/// start with one, or ends; the flag tells whether it ended.
+async fn read_head(recv: &mut RecvStream) -> Option<(Vec<u8>, bool)> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; MAX_REQUEST_LEN];
+
+    while head.len() < SINK.len().max(ECHO.len()) && (SINK.starts_with(&head) || ECHO.starts_with(&head)) {
+        match recv.read(&mut buf).await {
+            Ok(Some(n)) => head.extend_from_slice(&buf[..n]),
+            Ok(None) => return Some((head, true)),
+            Err(_) => return None,
+        }
+    }
+    Some((head, false))
+}
+
+/// Reads a stream to its end and returns how many bytes it carried.
+async fn drain(recv: &mut RecvStream) -> Option<u64> {
+    let mut count = 0;
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        match recv.read(&mut buf).await {
+            Ok(Some(n)) => count += n as u64,
+            Ok(None) => return Some(count),
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Writes `head` and then everything read from `recv` back to `send`.
+async fn echo(send: &mut SendStream, recv: &mut RecvStream, head: &[u8]) -> Option<()> {
+    send.write_all(head).await.ok()?;
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        match recv.read(&mut buf).await.ok()? {
+            Some(n) => send.write_all(&buf[..n]).await.ok()?,
+            None => return Some(()),
+        }
+    }
+}
+
+/// Reads a bidirectional ping after `request` until the stream ends or its data is
+/// `ping`, or can no longer be.
+async fn read_ping(recv: &mut RecvStream, mut request: Vec<u8>) -> Option<Vec<u8>> {
+    let mut buf = [0u8; MAX_REQUEST_LEN];
+
+    while request.len() < PING.len() && PING.starts_with(&request) {
+        match recv.read(&mut buf).await {
+            Ok(Some(n)) => request.extend_from_slice(&buf[..n]),
+            Ok(None) => return Some(request),
+            Err(_) => return None,
+        }
+    }
+    Some(request)
+}
+
+/// Reads the rest of a request after `request` until the stream ends, giving up on
+/// requests longer than any ping.
+async fn read_request(recv: &mut RecvStream, mut request: Vec<u8>) -> Option<Vec<u8>> {
+    let mut buf = [0u8; MAX_REQUEST_LEN];
+
+    while request.len() <= MAX_REQUEST_LEN {
+        match recv.read(&mut buf).await {
+            Ok(Some(n)) => request.extend_from_slice(&buf[..n]),
+            Ok(None) => return Some(request),
+            Err(_) => return None,
+        }
+    }
+    Some(request)
+}
+
+/// Serves plain HTTP/1.1 with `server` on a blocking thread: `/` answers with
+/// [`HOMEPAGE`], anything else with 404. Dropping the future stops the thread.
+///
+/// WebTransport sessions only carry `CONNECT` requests, so the homepage the Python server
+/// returns over HTTP/3 is offered over TCP here, which also suits plain health checks.
+pub async fn serve_http(server: tiny_http::Server) {
+    let server = Arc::new(server);
+    let _unblock = Unblock(server.clone());
+    let serving = {
+        let server = server.clone();
+        tokio::task::spawn_blocking(move || answer_http(&server))
+    };
+    let _ = serving.await;
+}
+
+fn answer_http(server: &tiny_http::Server) {
+    let content_type = Header::from_bytes("Content-Type", "text/plain").expect("valid header");
+    for request in server.incoming_requests() {
+        let response = match request.url() {
+            "/" => Response::from_string(HOMEPAGE),
+            _ => Response::from_string("Not Found").with_status_code(404),
+        };
+        let _ = request.respond(response.with_header(content_type.clone()));
+    }
+}
+
+/// Unblocks the thread serving HTTP when dropped.
+struct Unblock(Arc<tiny_http::Server>);
+
+impl Drop for Unblock {
+    fn drop(&mut self) {
+        self.0.unblock();
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::IsTerminal;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Parser;
+use wtransport::tls::rustls::pki_types::pem::PemObject;
+use wtransport::tls::rustls::pki_types::CertificateDer;
+use wtransport::tls::rustls::RootCertStore;
+use wtransport::Identity;
+
+use ping_pong_server_rs::serve_http;
+use ping_pong_server_rs::Server;
+
+/// WebTransport ping-pong server.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Load the TLS certificate from the specified file.
+    #[arg(short, long, requires = "private_key")]
+    certificate: Option<PathBuf>,
+
+    /// Load the TLS private key from the specified file.
+    #[arg(short = 'k', long, requires = "certificate")]
+    private_key: Option<PathBuf>,
+
+    /// Require clients to present a certificate issued by a CA of this PEM bundle.
+    #[arg(long, value_name = "PEM")]
+    client_ca: Option<PathBuf>,
+
+    /// Listen on the specified address.
+    #[arg(long, default_value = "::")]
+    host: IpAddr,
+
+    /// Listen on the specified port, for QUIC on UDP and plain HTTP on TCP.
+    #[arg(long, default_value_t = 4433)]
+    port: u16,
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .init();
+
+    let identity = match (&cli.certificate, &cli.private_key) {
+        (Some(certificate), Some(private_key)) => {
+            Identity::load_pemfiles(certificate, private_key).await.map_err(std::io::Error::other)?
+        }
+        _ => {
+            println!("No certificate given, using a self-signed one for localhost.");
+            Identity::self_signed(["localhost", "127.0.0.1", "::1"]).map_err(std::io::Error::other)?
+        }
+    };
+
+    let hash = identity.certificate_chain().as_slice()[0].hash();
+    let addr = SocketAddr::new(cli.host, cli.port);
+    let server = match &cli.client_ca {
+        Some(path) => Server::bind_with_client_auth(addr, identity, load_roots(path)?)?,
+        None => Server::bind(addr, identity)?,
+    };
+    let http = tiny_http::Server::http(addr).map_err(std::io::Error::other)?;
+
+    println!("Server is running on {}", server.local_addr()?);
+    println!("Certificate SHA-256: {}", hash);
+    tokio::join!(server.serve(), serve_http(http));
+    Ok(())
+}
+
+fn load_roots(path: &Path) -> std::io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for certificate in CertificateDer::pem_file_iter(path).map_err(std::io::Error::other)? {
+        roots.add(certificate.map_err(std::io::Error::other)?).map_err(std::io::Error::other)?;
+    }
+    Ok(roots)
+}
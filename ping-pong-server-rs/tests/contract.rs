@@ -0,0 +1,171 @@
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use wtransport::quinn;
+use wtransport::quinn::crypto::rustls::QuicClientConfig;
+use wtransport::tls::rustls;
+use wtransport::tls::rustls::crypto::ring;
+use wtransport::tls::rustls::pki_types::CertificateDer;
+use wtransport::tls::rustls::RootCertStore;
+use wtransport::ClientConfig;
+use wtransport::Connection;
+use wtransport::Endpoint;
+use wtransport::Identity;
+
+use ping_pong_server_rs::serve_http;
+use ping_pong_server_rs::Server;
+use ping_pong_server_rs::ECHO;
+use ping_pong_server_rs::HOMEPAGE;
+use ping_pong_server_rs::PING;
+use ping_pong_server_rs::PONG;
+use ping_pong_server_rs::QUACK;
+use ping_pong_server_rs::QUACK_ACK;
+use ping_pong_server_rs::SIDUCK_ALPN;
+use ping_pong_server_rs::SINK;
+
+/// Starts a server and returns its port and certificate.
+fn start_server() -> (u16, CertificateDer<'static>) {
+    let identity = Identity::self_signed(["localhost"]).unwrap();
+    let certificate = CertificateDer::from(identity.certificate_chain().as_slice()[0].der().to_vec());
+    let server = Server::bind(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0), identity).unwrap();
+    let port = server.local_addr().unwrap().port();
+    tokio::spawn(server.serve());
+    (port, certificate)
+}
+
+async fn connect() -> Connection {
+    let (port, _) = start_server();
+
+    let config = ClientConfig::builder()
+        .with_bind_address(SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0))
+        .with_no_cert_validation()
+        .build();
+    Endpoint::client(config)
+        .unwrap()
+        .connect(format!("https://[::1]:{}/", port))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn answers_ping_with_pong() {
+    let conn = connect().await;
+    let (mut send, mut recv) = conn.open_bi().await.unwrap().await.unwrap();
+
+    send.write_all(PING).await.unwrap();
+    send.finish().await.unwrap();
+
+    let mut reply = [0u8; 4];
+    recv.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, PONG);
+    assert_eq!(recv.read(&mut reply).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn answers_ping_before_the_stream_ends() {
+    let conn = connect().await;
+    let (mut send, mut recv) = conn.open_bi().await.unwrap().await.unwrap();
+
+    send.write_all(PING).await.unwrap();
+
+    let mut reply = [0u8; 4];
+    recv.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, PONG);
+}
+
+#[tokio::test]
+async fn echoes_datagrams() {
+    let conn = connect().await;
+
+    conn.send_datagram(b"ping:1").unwrap();
+    let datagram = conn.receive_datagram().await.unwrap();
+    assert_eq!(datagram.payload().as_ref(), b"ping:1");
+}
+
+#[tokio::test]
+async fn sink_answers_with_byte_count() {
+    let conn = connect().await;
+    let (mut send, mut recv) = conn.open_bi().await.unwrap().await.unwrap();
+
+    send.write_all(SINK).await.unwrap();
+    send.write_all(&[0u8; 10_000]).await.unwrap();
+    send.finish().await.unwrap();
+
+    let mut reply = Vec::new();
+    recv.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(reply, b"10000");
+}
+
+#[tokio::test]
+async fn echo_returns_stream_payload() {
+    let conn = connect().await;
+    let (mut send, mut recv) = conn.open_bi().await.unwrap().await.unwrap();
+
+    send.write_all(ECHO).await.unwrap();
+    send.write_all(b"hello").await.unwrap();
+    send.finish().await.unwrap();
+
+    let mut reply = Vec::new();
+    recv.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(reply, b"hello");
+}
+
+#[tokio::test]
+async fn answers_uni_ping_on_a_uni_stream() {
+    let conn = connect().await;
+    let mut send = conn.open_uni().await.unwrap().await.unwrap();
+
+    send.write_all(b"ping:7").await.unwrap();
+    send.finish().await.unwrap();
+
+    let mut recv = conn.accept_uni().await.unwrap();
+    let mut reply = Vec::new();
+    recv.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(reply, b"pong:7");
+}
+
+#[tokio::test]
+async fn answers_quack_with_quack_ack_on_siduck_connections() {
+    let (port, certificate) = start_server();
+    let mut roots = RootCertStore::empty();
+    roots.add(certificate).unwrap();
+    let mut tls = rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![SIDUCK_ALPN.to_vec()];
+    let mut endpoint = quinn::Endpoint::client(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0)).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls).unwrap())));
+    let conn = endpoint
+        .connect(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), port), "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+
+    conn.send_datagram(QUACK.into()).unwrap();
+    let reply = conn.read_datagram().await.unwrap();
+    assert_eq!(reply.as_ref(), QUACK_ACK);
+}
+
+#[tokio::test]
+async fn serves_the_homepage_over_http() {
+    let server = tiny_http::Server::http(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0)).unwrap();
+    let addr = server.server_addr().to_ip().unwrap();
+    tokio::spawn(serve_http(server));
+
+    for (path, status, body) in [("/", "200 OK", HOMEPAGE), ("/other", "404 Not Found", "Not Found")] {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with(&format!("HTTP/1.1 {}\r\n", status)), "{}", response);
+        assert!(response.ends_with(&format!("\r\n\r\n{}", body)), "{}", response);
+    }
+}